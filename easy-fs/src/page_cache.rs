@@ -0,0 +1,245 @@
+//! A small LRU file-page cache fronting `Inode::read_at`/`write_at`, modeled
+//! on the DragonOS page reclaimer: each entry buffers one 4 KiB logical file
+//! page keyed by `(ino, page_index)`. `read_at` fills misses from the block
+//! device through `DiskInode::read_at` and inserts them; `write_at` only
+//! marks the touched page dirty and updates it in place, so a run of small
+//! writes is batched instead of hitting `block_cache_sync_all` every time.
+//! A dirty page is written back through `DiskInode::write_at` only when it
+//! is evicted to make room, or when `Inode::sync`/`flush_all` asks for it
+//! explicitly.
+use super::{get_block_cache, BlockDevice, DiskInode};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+use spin::Mutex;
+
+/// Size of one logical file page the cache buffers in, independent of the
+/// underlying block size.
+pub const PAGE_SIZE: usize = 4096;
+/// Number of pages kept resident before the LRU evicts to make room.
+const CAPACITY: usize = 64;
+
+/// Where an inode's `DiskInode` struct lives on disk, so a cached page can
+/// be written back on eviction/flush without the `Inode` that filled it
+/// still being around.
+#[derive(Clone)]
+struct InodeLocation {
+    block_id: usize,
+    block_offset: usize,
+    block_device: Arc<dyn BlockDevice>,
+}
+
+/// Identifies a cached 4 KiB logical page of a file.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+struct PageKey {
+    ino: u64,
+    page_index: usize,
+}
+
+struct CachedPage {
+    data: [u8; PAGE_SIZE],
+    dirty: bool,
+}
+
+/// Global LRU page cache shared by every open `Inode`.
+pub struct PageCache {
+    pages: BTreeMap<PageKey, CachedPage>,
+    /// Least-recently-used key first
+    lru: Vec<PageKey>,
+    locations: BTreeMap<u64, InodeLocation>,
+}
+
+impl PageCache {
+    fn new() -> Self {
+        Self {
+            pages: BTreeMap::new(),
+            lru: Vec::new(),
+            locations: BTreeMap::new(),
+        }
+    }
+
+    /// Record where `ino`'s `DiskInode` lives, so its cached pages can be
+    /// written back later purely from this registry.
+    pub fn register(
+        &mut self,
+        ino: u64,
+        block_id: usize,
+        block_offset: usize,
+        block_device: Arc<dyn BlockDevice>,
+    ) {
+        self.locations.insert(
+            ino,
+            InodeLocation {
+                block_id,
+                block_offset,
+                block_device,
+            },
+        );
+    }
+
+    fn touch(&mut self, key: PageKey) {
+        if let Some(pos) = self.lru.iter().position(|k| *k == key) {
+            self.lru.remove(pos);
+        }
+        self.lru.push(key);
+    }
+
+    fn writeback(&self, key: PageKey, page: &CachedPage) {
+        let Some(loc) = self.locations.get(&key.ino) else {
+            // the inode was unlinked/invalidated out from under an
+            // in-flight eviction; nothing left to write back to
+            return;
+        };
+        get_block_cache(loc.block_id, Arc::clone(&loc.block_device))
+            .lock()
+            .modify(loc.block_offset, |disk_inode: &mut DiskInode| {
+                disk_inode.write_at(key.page_index * PAGE_SIZE, &page.data, &loc.block_device);
+            });
+    }
+
+    /// Evict the least-recently-used page, writing it back first if dirty.
+    fn evict_one(&mut self) {
+        if self.lru.is_empty() {
+            return;
+        }
+        let key = self.lru.remove(0);
+        if let Some(page) = self.pages.remove(&key) {
+            if page.dirty {
+                self.writeback(key, &page);
+            }
+        }
+    }
+
+    fn fetch(
+        &mut self,
+        key: PageKey,
+        disk_inode: &DiskInode,
+        block_device: &Arc<dyn BlockDevice>,
+    ) {
+        if self.pages.contains_key(&key) {
+            return;
+        }
+        if self.pages.len() >= CAPACITY {
+            self.evict_one();
+        }
+        let mut data = [0u8; PAGE_SIZE];
+        disk_inode.read_at(key.page_index * PAGE_SIZE, &mut data, block_device);
+        self.pages.insert(key, CachedPage { data, dirty: false });
+    }
+
+    /// Read `buf.len()` bytes starting at `offset` of inode `ino`, filling
+    /// any missed pages from `disk_inode`/`block_device` on the way.
+    pub fn read_at(
+        &mut self,
+        ino: u64,
+        offset: usize,
+        buf: &mut [u8],
+        disk_inode: &DiskInode,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> usize {
+        let mut read_size = 0;
+        while read_size < buf.len() {
+            let pos = offset + read_size;
+            let page_index = pos / PAGE_SIZE;
+            let page_offset = pos % PAGE_SIZE;
+            let key = PageKey { ino, page_index };
+            self.fetch(key, disk_inode, block_device);
+            self.touch(key);
+            let page = self.pages.get(&key).unwrap();
+            let copy_len = (PAGE_SIZE - page_offset).min(buf.len() - read_size);
+            if copy_len == 0 {
+                break;
+            }
+            buf[read_size..read_size + copy_len]
+                .copy_from_slice(&page.data[page_offset..page_offset + copy_len]);
+            read_size += copy_len;
+        }
+        read_size
+    }
+
+    /// Write `buf` at `offset` of inode `ino`, marking every touched page
+    /// dirty instead of syncing it to the block device right away.
+    pub fn write_at(
+        &mut self,
+        ino: u64,
+        offset: usize,
+        buf: &[u8],
+        disk_inode: &DiskInode,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> usize {
+        let mut written = 0;
+        while written < buf.len() {
+            let pos = offset + written;
+            let page_index = pos / PAGE_SIZE;
+            let page_offset = pos % PAGE_SIZE;
+            let key = PageKey { ino, page_index };
+            self.fetch(key, disk_inode, block_device);
+            self.touch(key);
+            let page = self.pages.get_mut(&key).unwrap();
+            let copy_len = (PAGE_SIZE - page_offset).min(buf.len() - written);
+            page.data[page_offset..page_offset + copy_len]
+                .copy_from_slice(&buf[written..written + copy_len]);
+            page.dirty = true;
+            written += copy_len;
+        }
+        written
+    }
+
+    /// Write back every dirty page belonging to `ino` (used by
+    /// `Inode::sync`), leaving them cached and now clean.
+    pub fn sync_inode(&mut self, ino: u64) {
+        let dirty_keys: Vec<PageKey> = self
+            .pages
+            .iter()
+            .filter(|(k, p)| k.ino == ino && p.dirty)
+            .map(|(k, _)| *k)
+            .collect();
+        for key in dirty_keys {
+            if let Some(page) = self.pages.get(&key) {
+                self.writeback(key, page);
+            }
+            if let Some(page) = self.pages.get_mut(&key) {
+                page.dirty = false;
+            }
+        }
+    }
+
+    /// Write back every dirty page in the cache, across every inode.
+    pub fn flush_all(&mut self) {
+        let inos: Vec<u64> = self.locations.keys().copied().collect();
+        for ino in inos {
+            self.sync_inode(ino);
+        }
+    }
+
+    /// Drop every cached page and the registered location of `ino`, so a
+    /// recycled inode number can never be served stale pages that used to
+    /// belong to it.
+    pub fn invalidate(&mut self, ino: u64) {
+        let stale_keys: Vec<PageKey> = self
+            .pages
+            .keys()
+            .filter(|k| k.ino == ino)
+            .copied()
+            .collect();
+        for key in stale_keys {
+            self.pages.remove(&key);
+            if let Some(pos) = self.lru.iter().position(|k| *k == key) {
+                self.lru.remove(pos);
+            }
+        }
+        self.locations.remove(&ino);
+    }
+}
+
+lazy_static! {
+    /// The global page cache shared by every open `Inode`.
+    pub static ref PAGE_CACHE: Mutex<PageCache> = Mutex::new(PageCache::new());
+}
+
+/// Write back every dirty page in [`PAGE_CACHE`]; analogous to
+/// `block_cache_sync_all` but at file-page granularity.
+pub fn flush_all() {
+    PAGE_CACHE.lock().flush_all();
+}
@@ -1,12 +1,16 @@
 use super::{
     block_cache_sync_all, get_block_cache, BlockDevice, DirEntry, DiskInode, DiskInodeType,
-    EasyFileSystem, DIRENT_SZ,
+    EasyFileSystem, DIRENT_SZ, PAGE_CACHE,
 };
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use spin::{Mutex, MutexGuard};
 
+/// Number of symlink redirects `resolve_path` will follow before giving up
+/// and reporting failure, so a symlink cycle can't loop it forever.
+const MAX_SYMLINK_HOPS: usize = 40;
+
 /// Virtual filesystem layer over easy-fs
 pub struct Inode {
     /// inode 文件所在 inode 编号
@@ -26,6 +30,11 @@ impl Inode {
         fs: Arc<Mutex<EasyFileSystem>>,
         block_device: Arc<dyn BlockDevice>,
     ) -> Self {
+        // so the page cache can write a dirty page of this file back on
+        // eviction/flush without this `Inode` itself being around
+        PAGE_CACHE
+            .lock()
+            .register(ino, block_id as usize, block_offset, Arc::clone(&block_device));
         Self {
             ino,
             block_id: block_id as usize,
@@ -285,6 +294,151 @@ impl Inode {
         )))
         // release efs lock automatically by compiler
     }
+
+    /// Create an empty subdirectory named `name` under the current inode.
+    /// Mirrors `create`, but the new inode is initialized as a
+    /// `DiskInodeType::Directory` instead of a `DiskInodeType::File`.
+    pub fn create_dir(&self, name: &str) -> Option<Arc<Inode>> {
+        let mut fs = self.fs.lock();
+        let op = |root_inode: &DiskInode| {
+            assert!(root_inode.is_dir());
+            self.find_inode_id(name, root_inode)
+        };
+        if self.read_disk_inode(op).is_some() {
+            return None;
+        }
+        let new_inode_id = fs.alloc_inode();
+        let (new_inode_block_id, new_inode_block_offset) = fs.get_disk_inode_pos(new_inode_id);
+        get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
+                new_inode.initialize(DiskInodeType::Directory);
+            });
+        self.modify_disk_inode(|root_inode| {
+            let file_count = (root_inode.size as usize) / DIRENT_SZ;
+            let new_size = (file_count + 1) * DIRENT_SZ;
+            self.increase_size(new_size as u32, root_inode, &mut fs);
+            let dirent = DirEntry::new(name, new_inode_id);
+            root_inode.write_at(
+                file_count * DIRENT_SZ,
+                dirent.as_bytes(),
+                &self.block_device,
+            );
+        });
+
+        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
+        block_cache_sync_all();
+        Some(Arc::new(Self::new(
+            new_inode_id as u64,
+            block_id,
+            block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+        )))
+    }
+
+    /// Create a symlink named `name` under the current inode, pointing at
+    /// `target`. Mirrors `create`, but the new inode is a
+    /// `DiskInodeType::Symlink` whose data block holds `target`'s bytes
+    /// instead of file content; read it back with `readlink`.
+    pub fn symlink(&self, name: &str, target: &str) -> Option<Arc<Inode>> {
+        let mut fs = self.fs.lock();
+        let op = |root_inode: &DiskInode| {
+            assert!(root_inode.is_dir());
+            self.find_inode_id(name, root_inode)
+        };
+        if self.read_disk_inode(op).is_some() {
+            return None;
+        }
+        let new_inode_id = fs.alloc_inode();
+        let (new_inode_block_id, new_inode_block_offset) = fs.get_disk_inode_pos(new_inode_id);
+        get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
+                new_inode.initialize(DiskInodeType::Symlink);
+            });
+        self.modify_disk_inode(|root_inode| {
+            let file_count = (root_inode.size as usize) / DIRENT_SZ;
+            let new_size = (file_count + 1) * DIRENT_SZ;
+            self.increase_size(new_size as u32, root_inode, &mut fs);
+            let dirent = DirEntry::new(name, new_inode_id);
+            root_inode.write_at(
+                file_count * DIRENT_SZ,
+                dirent.as_bytes(),
+                &self.block_device,
+            );
+        });
+
+        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
+        let link_inode = Self::new(
+            new_inode_id as u64,
+            block_id,
+            block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+        );
+        // write the target path straight through `DiskInode::write_at`
+        // rather than `Inode::write_at`, which would try to re-lock `fs`
+        let target_bytes = target.as_bytes();
+        link_inode.modify_disk_inode(|disk_inode| {
+            link_inode.increase_size(target_bytes.len() as u32, disk_inode, &mut fs);
+            disk_inode.write_at(0, target_bytes, &link_inode.block_device);
+        });
+        block_cache_sync_all();
+        Some(Arc::new(link_inode))
+    }
+
+    /// Is this inode a symlink?
+    pub fn is_symlink(&self) -> bool {
+        self.read_disk_inode(|disk_inode| disk_inode.is_symlink())
+    }
+
+    /// Read back the target path stored in a symlink's data block.
+    pub fn readlink(&self) -> String {
+        let size = self.read_disk_inode(|disk_inode| disk_inode.size) as usize;
+        let mut buf = alloc::vec![0u8; size];
+        self.read_at(0, &mut buf);
+        String::from_utf8_lossy(&buf).into_owned()
+    }
+
+    /// Follow `name` from the current (directory) inode, transparently
+    /// resolving through any chain of symlinks it names instead of
+    /// returning the symlink inode itself. `hops` is the shared
+    /// cycle-breaking budget threaded through a whole `resolve_path` call.
+    fn find_follow(&self, name: &str, hops: &mut usize) -> Option<Arc<Inode>> {
+        let inode = self.find(name)?;
+        if inode.is_symlink() {
+            *hops += 1;
+            if *hops > MAX_SYMLINK_HOPS {
+                return None;
+            }
+            let target = inode.readlink();
+            return self.resolve_path_hops(&target, hops);
+        }
+        Some(inode)
+    }
+
+    /// Resolve a `/`-separated path, walking directory inodes component by
+    /// component starting from `self` and transparently following any
+    /// symlink encountered along the way. Returns `None` if a component is
+    /// missing, or if more than `MAX_SYMLINK_HOPS` symlinks have to be
+    /// followed (a cycle).
+    pub fn resolve_path(&self, path: &str) -> Option<Arc<Inode>> {
+        self.resolve_path_hops(path, &mut 0)
+    }
+
+    fn resolve_path_hops(&self, path: &str, hops: &mut usize) -> Option<Arc<Inode>> {
+        let mut current: Option<Arc<Inode>> = None;
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            let next = match &current {
+                Some(dir) => dir.find_follow(component, hops)?,
+                None => self.find_follow(component, hops)?,
+            };
+            current = Some(next);
+        }
+        current
+    }
+
     /// List inodes under current inode
     pub fn ls(&self) -> Vec<String> {
         let _fs = self.fs.lock();
@@ -304,20 +458,35 @@ impl Inode {
     }
     /// Read data from current inode
     /// 根据inode找到文件数据所在的磁盘数据块，并读到内存中
+    ///
+    /// Goes through the global page cache rather than `DiskInode::read_at`
+    /// directly, so a hot file's data blocks aren't re-walked on every call.
     pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
         let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| disk_inode.read_at(offset, buf, &self.block_device))
+        self.read_disk_inode(|disk_inode| {
+            PAGE_CACHE
+                .lock()
+                .read_at(self.ino, offset, buf, disk_inode, &self.block_device)
+        })
     }
     /// Write data to current inode
     /// 根据inode找到文件数据所在的磁盘数据块，把内存中数据写入到磁盘数据块中
+    ///
+    /// Only marks the touched pages of the page cache dirty; it does not
+    /// sync them to the block device immediately (see [`Self::sync`]), so a
+    /// run of small writes is batched into one writeback instead of many.
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
         let mut fs = self.fs.lock();
-        let size = self.modify_disk_inode(|disk_inode| {
+        self.modify_disk_inode(|disk_inode| {
             self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
-            disk_inode.write_at(offset, buf, &self.block_device)
-        });
-        block_cache_sync_all();
-        size
+            PAGE_CACHE
+                .lock()
+                .write_at(self.ino, offset, buf, disk_inode, &self.block_device)
+        })
+    }
+    /// Write back every dirty page cache entry belonging to this inode.
+    pub fn sync(&self) {
+        PAGE_CACHE.lock().sync_inode(self.ino);
     }
     /// Clear the data in current inode
     pub fn clear(&self) {
@@ -330,6 +499,10 @@ impl Inode {
                 fs.dealloc_data(data_block);
             }
         });
+        // the data blocks just freed may be reused by a different inode
+        // right away, so any page still cached under this `ino` must be
+        // dropped now rather than risk serving stale bytes later
+        PAGE_CACHE.lock().invalidate(self.ino);
         block_cache_sync_all();
     }
 }
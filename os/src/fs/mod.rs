@@ -0,0 +1,17 @@
+//! File trait & implementations used for reading/writing a process's file
+//! descriptor table, the foundation for `read`/`write`/`open`/`close`/`dup`/`pipe`.
+mod stdio;
+
+use crate::mm::UserBuffer;
+
+pub use stdio::{Stdin, Stdout};
+
+/// A readable/writable byte-stream resource installed into a process's
+/// `fd_table`; implementors back the actual syscalls with their own storage
+/// (a console, a pipe, a disk inode, ...).
+pub trait File: Send + Sync {
+    /// Read data from this file into `buf`, returning the number of bytes read
+    fn read(&self, buf: UserBuffer) -> usize;
+    /// Write data from `buf` into this file, returning the number of bytes written
+    fn write(&self, buf: UserBuffer) -> usize;
+}
@@ -4,6 +4,7 @@
 use super::{PhysAddr, PhysPageNum};
 use crate::config::MEMORY_END;
 use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 use core::fmt::{self, Debug, Formatter};
 use lazy_static::*;
@@ -39,60 +40,170 @@ impl Drop for FrameTracker {
     }
 }
 
+/// A run of `frames.len()` physically contiguous frames, e.g. for device DMA
+/// descriptors, freed as a single unit on `Drop`.
+pub struct FrameRunTracker {
+    /// physical page numbers making up the contiguous run, in ascending order
+    pub frames: Vec<PhysPageNum>,
+}
+
+impl Drop for FrameRunTracker {
+    fn drop(&mut self) {
+        if let Some(&start) = self.frames.first() {
+            frame_dealloc_contiguous(start, self.frames.len());
+        }
+    }
+}
+
 // 物理页帧管理器rust顶层抽象, 不管哪个一个分配器都要实现这个trait，与具体的分配器解耦
 trait FrameAllocator {
     fn new() -> Self;
     fn alloc(&mut self) -> Option<PhysPageNum>;
     fn dealloc(&mut self, ppn: PhysPageNum);
+    /// Allocate `n` physically contiguous frames, returned in ascending ppn order.
+    fn alloc_contiguous(&mut self, n: usize) -> Option<Vec<PhysPageNum>>;
+    /// Free a run of `n` physically contiguous frames starting at `ppn`,
+    /// previously handed out by `alloc_contiguous` (or `alloc` when `n == 1`).
+    fn dealloc_contiguous(&mut self, ppn: PhysPageNum, n: usize);
 }
-/// an implementation for frame allocator
-pub struct StackFrameAllocator {
-    current: usize, //空闲内存的起始物理页号
-    end: usize, //空闲内存的结束物理页号
-    recycled: Vec<usize>, //回收的物理页号
+
+/// Number of buddy orders tracked; order `k` blocks are `2^k` frames, so 32
+/// orders comfortably covers any amount of physical memory QEMU gives us.
+const MAX_ORDER: usize = 32;
+
+/// A buddy-system frame allocator.
+///
+/// The managed region `[0, total)` (frame numbers relative to `base`) is
+/// carved at `init` time into the largest power-of-two blocks that fit, one
+/// free list per order. `alloc`/`dealloc` are just `alloc_contiguous(1)`/
+/// `dealloc_contiguous(ppn, 1)` so the common single-frame path still goes
+/// through order-0 blocks and `FrameTracker` behavior is unchanged; the only
+/// difference from the old stack allocator is that recycled pages can now
+/// coalesce back into larger blocks instead of fragmenting forever.
+pub struct BuddyFrameAllocator {
+    /// physical page number of frame 0 in this allocator
+    base: usize,
+    /// total number of frames managed, for bounds checking on free
+    total: usize,
+    /// `free_lists[order]` holds the (base-relative) start offsets of every
+    /// free block of size `2^order` frames
+    free_lists: [Vec<usize>; MAX_ORDER],
+    /// order of every block currently on loan, keyed by its (base-relative)
+    /// start offset, so `dealloc_contiguous` knows how far to coalesce
+    allocated: BTreeMap<usize, usize>,
 }
 
-impl StackFrameAllocator {
+impl BuddyFrameAllocator {
+    /// Smallest order `k` such that `2^k >= n`
+    fn order_for(n: usize) -> usize {
+        let mut order = 0;
+        while (1usize << order) < n {
+            order += 1;
+        }
+        order
+    }
+
     pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
-        self.current = l.0;
-        self.end = r.0;
-        // trace!("last {} Physical Frames.", self.end - self.current);
+        self.base = l.0;
+        self.total = r.0 - l.0;
+        self.allocated = BTreeMap::new();
+        for list in self.free_lists.iter_mut() {
+            list.clear();
+        }
+        // Carve [0, total) into the largest power-of-two blocks that fit,
+        // greedily from the front, so every frame ends up on some free list.
+        let mut offset = 0;
+        while offset < self.total {
+            let remaining = self.total - offset;
+            // largest order whose block both fits in `remaining` and keeps
+            // `offset` aligned to its own size (required for buddy coalescing)
+            let mut order = MAX_ORDER - 1;
+            while order > 0 && ((1usize << order) > remaining || offset % (1usize << order) != 0)
+            {
+                order -= 1;
+            }
+            self.free_lists[order].push(offset);
+            offset += 1usize << order;
+        }
+    }
+
+    /// Pop a free block of exactly `order`, splitting a larger one if needed.
+    fn alloc_order(&mut self, order: usize) -> Option<usize> {
+        let mut cur = order;
+        while cur < MAX_ORDER && self.free_lists[cur].is_empty() {
+            cur += 1;
+        }
+        if cur >= MAX_ORDER {
+            return None;
+        }
+        while cur > order {
+            let block = self.free_lists[cur].pop().unwrap();
+            cur -= 1;
+            let buddy = block + (1usize << cur);
+            self.free_lists[cur].push(buddy);
+            self.free_lists[cur].push(block);
+        }
+        let block = self.free_lists[order].pop().unwrap();
+        self.allocated.insert(block, order);
+        Some(block)
+    }
+
+    /// Return a block to its free list, coalescing with its buddy up the
+    /// order chain for as long as the buddy is also free.
+    fn free_order(&mut self, mut block: usize, mut order: usize) {
+        while order + 1 < MAX_ORDER {
+            let buddy = block ^ (1usize << order);
+            if buddy + (1usize << order) > self.total {
+                break;
+            }
+            match self.free_lists[order].iter().position(|&b| b == buddy) {
+                Some(pos) => {
+                    self.free_lists[order].remove(pos);
+                    block = block.min(buddy);
+                    order += 1;
+                }
+                None => break,
+            }
+        }
+        self.free_lists[order].push(block);
     }
 }
-impl FrameAllocator for StackFrameAllocator {
+
+impl FrameAllocator for BuddyFrameAllocator {
     fn new() -> Self {
         Self {
-            current: 0,
-            end: 0,
-            recycled: Vec::new(),
+            base: 0,
+            total: 0,
+            free_lists: core::array::from_fn(|_| Vec::new()),
+            allocated: BTreeMap::new(),
         }
     }
     fn alloc(&mut self) -> Option<PhysPageNum> {
-        // 先优先从回收的物理页号中分配
-        if let Some(ppn) = self.recycled.pop() {
-            Some(ppn.into())
-        } else if self.current == self.end {
-            None // no more free frames to allocate
-        } else {
-            self.current += 1;
-            Some((self.current - 1).into())
-        }
+        self.alloc_order(0).map(|off| (self.base + off).into())
     }
     fn dealloc(&mut self, ppn: PhysPageNum) {
-        let ppn = ppn.0;
-        // validity check
-        if ppn >= self.current || self.recycled.iter().any(|&v| v == ppn) {
-            panic!("Frame ppn={:#x} has not been allocated!", ppn);
-        }
-        // recycle
-        self.recycled.push(ppn);
+        self.dealloc_contiguous(ppn, 1);
+    }
+    fn alloc_contiguous(&mut self, n: usize) -> Option<Vec<PhysPageNum>> {
+        let order = Self::order_for(n);
+        let block = self.alloc_order(order)?;
+        Some((0..n).map(|i| (self.base + block + i).into()).collect())
+    }
+    fn dealloc_contiguous(&mut self, ppn: PhysPageNum, n: usize) {
+        let block = ppn.0 - self.base;
+        let order = self
+            .allocated
+            .remove(&block)
+            .unwrap_or_else(|| panic!("Frame ppn={:#x} has not been allocated!", ppn.0));
+        debug_assert_eq!(1usize << order, n.next_power_of_two().max(1));
+        self.free_order(block, order);
     }
 }
 
 // type alias for frame allocator
-type FrameAllocatorImpl = StackFrameAllocator;
+type FrameAllocatorImpl = BuddyFrameAllocator;
 
-// 创建一个全局的StackFrameAllocator实例
+// 创建一个全局的BuddyFrameAllocator实例
 lazy_static! {
     /// frame allocator instance through lazy_static!
     pub static ref FRAME_ALLOCATOR: UPSafeCell<FrameAllocatorImpl> =
@@ -123,11 +234,70 @@ pub fn frame_alloc() -> Option<FrameTracker> {
         .map(FrameTracker::new)
 }
 
+/// Allocate `n` physically contiguous frames (e.g. for a DMA descriptor
+/// ring), returned as one `FrameRunTracker` that frees the whole run on drop.
+pub fn frame_alloc_contiguous(n: usize) -> Option<FrameRunTracker> {
+    let frames = FRAME_ALLOCATOR.exclusive_access().alloc_contiguous(n)?;
+    for &ppn in &frames {
+        for byte in ppn.get_bytes_array() {
+            *byte = 0;
+        }
+    }
+    Some(FrameRunTracker { frames })
+}
+
 /// Deallocate a physical page frame with a given ppn
 pub fn frame_dealloc(ppn: PhysPageNum) {
     FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
 }
 
+/// Deallocate a run of `n` physically contiguous frames starting at `ppn`
+fn frame_dealloc_contiguous(ppn: PhysPageNum, n: usize) {
+    FRAME_ALLOCATOR
+        .exclusive_access()
+        .dealloc_contiguous(ppn, n);
+}
+
+lazy_static! {
+    /// Extra-reference count for frames shared copy-on-write between address
+    /// spaces, keyed by ppn. A frame not in this map has exactly one owner
+    /// (its `FrameTracker`); `fork`ing a writable user page into a COW
+    /// mapping inserts it here with count 2, and every further address space
+    /// that maps the same frame bumps it by one. The owning `FrameTracker`
+    /// still frees the frame on drop as usual — this map only tells a store
+    /// page fault whether it is safe to reuse the frame in place (count
+    /// would drop to 0) or whether it must copy onto a fresh one first.
+    static ref COW_REFCOUNTS: UPSafeCell<BTreeMap<usize, usize>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Mark `ppn` as shared copy-on-write by one more address space.
+pub fn cow_frame_add_ref(ppn: PhysPageNum) {
+    let mut counts = COW_REFCOUNTS.exclusive_access();
+    let count = counts.entry(ppn.0).or_insert(1);
+    *count += 1;
+}
+
+/// Number of address spaces currently sharing `ppn` copy-on-write; 1 if it
+/// is not shared.
+pub fn cow_frame_ref_count(ppn: PhysPageNum) -> usize {
+    *COW_REFCOUNTS.exclusive_access().get(&ppn.0).unwrap_or(&1)
+}
+
+/// Record that one address space is done sharing `ppn` (it copied onto a
+/// fresh frame, or unmapped it). Once the count drops to 1 the frame is no
+/// longer considered shared and the entry is removed; the remaining owner's
+/// `FrameTracker` is responsible for eventually freeing it.
+pub fn cow_frame_dec_ref(ppn: PhysPageNum) {
+    let mut counts = COW_REFCOUNTS.exclusive_access();
+    if let Some(count) = counts.get_mut(&ppn.0) {
+        *count -= 1;
+        if *count <= 1 {
+            counts.remove(&ppn.0);
+        }
+    }
+}
+
 #[allow(unused)]
 /// a simple test for frame allocator
 pub fn frame_allocator_test() {
@@ -146,3 +316,13 @@ pub fn frame_allocator_test() {
     drop(v);
     println!("frame_allocator_test passed!");
 }
+
+#[allow(unused)]
+/// a simple test for the contiguous allocation path
+pub fn frame_allocator_contiguous_test() {
+    let run = frame_alloc_contiguous(8).unwrap();
+    for pair in run.frames.windows(2) {
+        assert_eq!(pair[1].0, pair[0].0 + 1, "frames must be contiguous");
+    }
+    println!("frame_allocator_contiguous_test passed!");
+}
@@ -1,5 +1,9 @@
 //! Implementation of [`PageTableEntry`] and [`PageTable`].
-use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
+use super::{
+    cow_frame_add_ref, cow_frame_dec_ref, cow_frame_ref_count, frame_alloc, FrameTracker,
+    PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum,
+};
+use crate::config::PAGE_SIZE;
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
@@ -7,7 +11,12 @@ use bitflags::*;
 
 bitflags! {
     /// page table entry flags
-    pub struct PTEFlags: u8 {
+    ///
+    /// Bits 0-7 are the Sv39-defined V/R/W/X/U/G/A/D flags; bits 8-9 are the
+    /// two RSW (reserved-for-software) bits the hardware never looks at, one
+    /// of which `COW` borrows to mark a copy-on-write page (see
+    /// `PageTable::fork_cow`).
+    pub struct PTEFlags: u16 {
         const V = 1 << 0;
         const R = 1 << 1;
         const W = 1 << 2;
@@ -16,6 +25,10 @@ bitflags! {
         const G = 1 << 5;
         const A = 1 << 6;
         const D = 1 << 7;
+        /// RSW bit 8: set on a writable user leaf that `fork_cow` shared
+        /// read-only between parent and child instead of copying; cleared
+        /// again (with `W` restored) once a store fault copies the page.
+        const COW = 1 << 8;
     }
 }
 
@@ -47,7 +60,8 @@ impl PageTableEntry {
     }
     /// Get the flags from the page table entry
     pub fn flags(&self) -> PTEFlags {
-        PTEFlags::from_bits(self.bits as u8).unwrap()
+        // low 10 bits: the 8 Sv39 flag bits plus the 2 RSW bits `COW` lives in
+        PTEFlags::from_bits((self.bits as u16) & 0x3ff).unwrap()
     }
     /// The page pointered by page table entry is valid?
     pub fn is_valid(&self) -> bool {
@@ -65,12 +79,69 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    /// A valid PTE with any of R/W/X set is a *leaf* wherever it appears in
+    /// the walk — in Sv39 that can happen at level 1 (a 2 MiB megapage) or
+    /// level 2/the root level (a 1 GiB gigapage), not just at level 0.
+    pub fn is_leaf(&self) -> bool {
+        self.is_valid() && (self.flags() & (PTEFlags::R | PTEFlags::W | PTEFlags::X)) != PTEFlags::empty()
+    }
+}
+
+/// The granularity of a mapping in Sv39: a normal 4 KiB leaf at level 0, or a
+/// huge mapping that stops descending one or two levels early.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PageSize {
+    /// 4 KiB page, leaf at level 0
+    Size4K,
+    /// 2 MiB megapage, leaf at level 1
+    Size2M,
+    /// 1 GiB gigapage, leaf at level 2 (the root level)
+    Size1G,
+}
+
+impl PageSize {
+    /// The page-table level (0, 1 or 2, matching the index into
+    /// `VirtPageNum::indexes()`) at which a mapping of this size writes its
+    /// leaf PTE.
+    fn leaf_level(self) -> usize {
+        match self {
+            PageSize::Size4K => 2,
+            PageSize::Size2M => 1,
+            PageSize::Size1G => 0,
+        }
+    }
+    /// The level at which `find_pte`'s walk found a leaf PTE.
+    fn from_level(level: usize) -> Self {
+        match level {
+            0 => PageSize::Size1G,
+            1 => PageSize::Size2M,
+            2 => PageSize::Size4K,
+            _ => unreachable!("Sv39 page tables are only 3 levels deep"),
+        }
+    }
+    /// Number of low VPN/PPN index bits that must be zero for a mapping of
+    /// this size to be aligned (9 bits per Sv39 level skipped).
+    fn align_bits(self) -> usize {
+        match self {
+            PageSize::Size4K => 0,
+            PageSize::Size2M => 9,
+            PageSize::Size1G => 18,
+        }
+    }
+    /// Width in bits of the in-page byte offset a page of this size covers;
+    /// `translate_va` uses this instead of the fixed 12-bit `page_offset()`
+    /// once it has found a wide leaf.
+    fn offset_bits(self) -> usize {
+        12 + self.align_bits()
+    }
 }
 
 /// page table structure
 pub struct PageTable {
     root_ppn: PhysPageNum, // 根页表的物理页号
-    frames: Vec<FrameTracker>, // 保存了页表所有的节点（包括根节点）所在的物理页帧
+    // 保存了页表所有的节点（包括根节点）所在的物理页帧，以及这个页表独占持有的数据页帧
+    // （比如 COW 缺页异常拷贝出来的新页）；与其它地址空间共享的页帧由共享方的 FrameTracker 持有
+    frames: Vec<FrameTracker>,
 }
 
 /// Assume that it won't oom when creating/mapping.
@@ -80,7 +151,7 @@ impl PageTable {
         let frame = frame_alloc().unwrap(); // 分配一个物理页帧
         PageTable {
             root_ppn: frame.ppn, // 根页表的物理页号
-            frames: vec![frame], 
+            frames: vec![frame],
         }
     }
     /// Temporarily used to get arguments from user space.
@@ -90,17 +161,19 @@ impl PageTable {
             frames: Vec::new(),
         }
     }
-    /// Find PageTableEntry by VirtPageNum, create a frame for a 4KB page table if not exist
-    /// 通过虚拟页号vpn查找页表项，如果不存在则创建一个4KB的页表
-    fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+    /// Find the PTE for `vpn` at `level` (0, 1 or 2), creating a frame for
+    /// each intermediate page table it has to descend through that does
+    /// not exist yet. Stopping before level 2 is what lets [`Self::map_huge`]
+    /// write a megapage/gigapage leaf instead of always bottoming out at a
+    /// 4 KiB page.
+    fn find_pte_create_at(&mut self, vpn: VirtPageNum, level: usize) -> &mut PageTableEntry {
         let idxs = vpn.indexes(); // 获得页表项的索引
         let mut ppn = self.root_ppn; // 根页表的物理页号, 是物理页号，页号
         let mut result: Option<&mut PageTableEntry> = None; // 页表项
         for (i, idx) in idxs.iter().enumerate() {
             // 获得页表项
             let pte = &mut ppn.get_pte_array()[*idx];
-            // 如果是第三级页表项，直接返回
-            if i == 2 {
+            if i == level {
                 result = Some(pte);
                 break;
             }
@@ -113,61 +186,209 @@ impl PageTable {
             // 获得下一级页表的物理页号
             ppn = pte.ppn();
         }
-        result
+        result.unwrap()
     }
-    
-    /// Find PageTableEntry by VirtPageNum
-    /// 通过虚拟页号vpn查找页表项
-    fn find_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+
+    /// Find PageTableEntry by VirtPageNum, create a frame for a 4KB page table if not exist
+    /// 通过虚拟页号vpn查找页表项，如果不存在则创建一个4KB的页表
+    fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        Some(self.find_pte_create_at(vpn, PageSize::Size4K.leaf_level()))
+    }
+
+    /// Find the PTE mapping `vpn`, stopping early if the walk hits a leaf
+    /// (valid PTE with R/W/X set) before level 2 — a megapage or gigapage —
+    /// instead of always descending to a 4 KiB leaf. Returns the size of
+    /// the leaf found alongside the entry so callers like `translate_va`
+    /// can recover the right in-page offset width.
+    fn find_pte(&self, vpn: VirtPageNum) -> Option<(PageSize, &mut PageTableEntry)> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
-        let mut result: Option<&mut PageTableEntry> = None;
         for (i, idx) in idxs.iter().enumerate() {
             // 获得页表项
             let pte = &mut ppn.get_pte_array()[*idx]; // 每次都得转化成物理地址去查找页表项
-            if i == 2 {
-                result = Some(pte);
-                break;
+            if i == 2 || pte.is_leaf() {
+                return if pte.is_valid() {
+                    Some((PageSize::from_level(i), pte))
+                } else {
+                    None
+                };
             }
             if !pte.is_valid() {
                 return None;
             }
             ppn = pte.ppn();
         }
-        result
+        unreachable!("Sv39 page tables are only 3 levels deep")
     }
-    
+
     // 动态维护一个虚拟页号到页表项的映射，支持插入/删除键值对
-    
+
     /// 通过虚拟页号vpn映射到物理页号ppn
     /// set the map between virtual page number and physical page number
-    #[allow(unused)]
     pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
         let pte = self.find_pte_create(vpn).unwrap();
         assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
     }
-    
+
+    /// Map a 2 MiB megapage or 1 GiB gigapage in one PTE instead of 512 (or
+    /// 512*512) 4 KiB ones, so large contiguous regions like a kernel
+    /// identity map or a framebuffer cost far fewer PTEs and TLB entries.
+    /// Both `vpn` and `ppn` must already be aligned to `size` (their low 9
+    /// or 18 index bits zero) — an unaligned huge mapping would silently
+    /// straddle and alias neighbouring pages, so this asserts rather than
+    /// rounding. Reached from [`Self::fork_cow`] when it walks onto a leaf
+    /// some other huge mapping already installed, so the child keeps the
+    /// same superpage instead of the parent's huge leaf getting shattered
+    /// into 4 KiB ones on fork.
+    pub fn map_huge(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, size: PageSize, flags: PTEFlags) {
+        let align_mask = (1usize << size.align_bits()) - 1;
+        assert_eq!(vpn.0 & align_mask, 0, "vpn {:?} is not aligned to {:?}", vpn, size);
+        assert_eq!(ppn.0 & align_mask, 0, "ppn {:?} is not aligned to {:?}", ppn, size);
+        let pte = self.find_pte_create_at(vpn, size.leaf_level());
+        assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+    }
+
     /// 通过虚拟页号vpn删除映射
     /// remove the map between virtual page number and physical page number
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) {
-        let pte = self.find_pte(vpn).unwrap();
+        let (_, pte) = self.find_pte(vpn).unwrap();
         assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
         *pte = PageTableEntry::empty();
     }
+
+    /// Walk every level-0/1/2 leaf currently mapped in this table, returning
+    /// `(vpn, ppn, flags, size)` for each. Used by [`Self::fork_cow`] to
+    /// enumerate the pages a child address space needs to share.
+    fn collect_leaves(&self) -> Vec<(VirtPageNum, PhysPageNum, PTEFlags, PageSize)> {
+        let mut out = Vec::new();
+        self.walk_leaves(self.root_ppn, 0, 0, &mut out);
+        out
+    }
+
+    fn walk_leaves(
+        &self,
+        ppn: PhysPageNum,
+        level: usize,
+        vpn_prefix: usize,
+        out: &mut Vec<(VirtPageNum, PhysPageNum, PTEFlags, PageSize)>,
+    ) {
+        for (idx, pte) in ppn.get_pte_array().iter().enumerate() {
+            if !pte.is_valid() {
+                continue;
+            }
+            let vpn_prefix = (vpn_prefix << 9) | idx;
+            if level == 2 || pte.is_leaf() {
+                let vpn = VirtPageNum::from(vpn_prefix << ((2 - level) * 9));
+                out.push((vpn, pte.ppn(), pte.flags(), PageSize::from_level(level)));
+            } else {
+                self.walk_leaves(pte.ppn(), level + 1, vpn_prefix, out);
+            }
+        }
+    }
+
+    /// Overwrite the flags of the leaf PTE already mapping `vpn` at `size`,
+    /// keeping its ppn. Unlike `map`/`map_huge` this expects the PTE to
+    /// already be valid — it is how `fork_cow` flips a parent's own leaf to
+    /// read-only-plus-`COW` without unmapping and remapping it.
+    fn rewrite_leaf(&mut self, vpn: VirtPageNum, size: PageSize, flags: PTEFlags) {
+        let pte = self.find_pte_create_at(vpn, size.leaf_level());
+        let ppn = pte.ppn();
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+    }
+
+    /// Clone every leaf mapping into `child` as copy-on-write instead of
+    /// copying frames: a writable user leaf has `W` cleared and the `COW`
+    /// RSW bit set in both this table's PTE and the one installed in
+    /// `child` (same `PhysPageNum`), and the frame's global refcount
+    /// (`cow_frame_add_ref`) is bumped so a later store fault on either side
+    /// knows it is shared. Read-only or kernel-only leaves (nothing to
+    /// protect) are just shared as-is. `child` must be empty of user
+    /// mappings before this is called.
+    pub fn fork_cow(&mut self, child: &mut PageTable) {
+        for (vpn, ppn, flags, size) in self.collect_leaves() {
+            let is_writable_user =
+                (flags & PTEFlags::W) != PTEFlags::empty() && (flags & PTEFlags::U) != PTEFlags::empty();
+            let is_already_cow = (flags & PTEFlags::COW) != PTEFlags::empty();
+            let child_flags = if is_writable_user {
+                let cow_bits = (flags.bits & !PTEFlags::W.bits) | PTEFlags::COW.bits;
+                let cow_flags = PTEFlags::from_bits(cow_bits).unwrap();
+                self.rewrite_leaf(vpn, size, cow_flags);
+                cow_frame_add_ref(ppn);
+                cow_flags
+            } else {
+                // already shared by an earlier fork (re-forking a child
+                // whose own leaves are already COW): a third mapping onto
+                // the same frame still needs the refcount bumped, even
+                // though this table's own PTE needs no rewrite
+                if is_already_cow {
+                    cow_frame_add_ref(ppn);
+                }
+                flags
+            };
+            match size {
+                PageSize::Size4K => child.map(vpn, ppn, child_flags),
+                _ => child.map_huge(vpn, ppn, size, child_flags),
+            }
+        }
+    }
+
+    /// Fix up a store page fault at `vpn` whose PTE has the `COW` bit set
+    /// (installed by `fork_cow`): allocate a fresh frame, copy the shared
+    /// page's bytes into it, and rewrite the PTE to point at the new frame
+    /// with `W` restored and `COW` cleared, decrementing the old frame's
+    /// share count. If nothing else is sharing the old frame any more
+    /// (`cow_frame_ref_count` is already back down to 1, e.g. a sibling
+    /// fault got there first), reuses it in place instead of copying.
+    /// Returns `false` if `vpn` has no PTE or the PTE is not marked `COW` —
+    /// a real permission fault the caller must handle some other way.
+    pub fn handle_cow_fault(&mut self, vpn: VirtPageNum) -> bool {
+        let Some((_, pte)) = self.find_pte(vpn) else {
+            return false;
+        };
+        let flags = pte.flags();
+        if (flags & PTEFlags::COW) == PTEFlags::empty() {
+            return false;
+        }
+        let old_ppn = pte.ppn();
+        let restored_bits = (flags.bits & !PTEFlags::COW.bits) | PTEFlags::W.bits;
+        let restored_flags = PTEFlags::from_bits(restored_bits).unwrap();
+        if cow_frame_ref_count(old_ppn) <= 1 {
+            *pte = PageTableEntry::new(old_ppn, restored_flags);
+            return true;
+        }
+        let frame = frame_alloc().unwrap();
+        let new_ppn = frame.ppn;
+        new_ppn
+            .get_bytes_array()
+            .copy_from_slice(old_ppn.get_bytes_array());
+        self.frames.push(frame);
+        cow_frame_dec_ref(old_ppn);
+        let Some((_, pte)) = self.find_pte(vpn) else {
+            unreachable!("vpn was just found above");
+        };
+        *pte = PageTableEntry::new(new_ppn, restored_flags);
+        true
+    }
+
     /// get the page table entry from the virtual page number
     /// 如果能够找到页表项，那么它会将页表项拷贝一份并返回，否则就返回一个 None 。
     /// 这个方法的主要作用是为了在内核中查找页表项，然后将页表项拷贝到内核中，以便内核能够访问到页表项。
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.find_pte(vpn).map(|pte| *pte)
+        self.find_pte(vpn).map(|(_, pte)| *pte)
     }
     /// get the physical address from the virtual address
     pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
-        self.find_pte(va.clone().floor()).map(|pte| {
+        // captured before `find_pte` consumes `va` into a `VirtPageNum`;
+        // `page_offset()` alone only ever sees the low 12 bits, which isn't
+        // enough once the leaf found is a 2 MiB/1 GiB superpage
+        let va_usize: usize = va.clone().into();
+        self.find_pte(va.floor()).map(|(size, pte)| {
             let aligned_pa: PhysAddr = pte.ppn().into();
-            let offset = va.page_offset();
             let aligned_pa_usize: usize = aligned_pa.into();
+            let offset = va_usize & ((1usize << size.offset_bits()) - 1);
             (aligned_pa_usize + offset).into()
         })
     }
@@ -175,43 +396,148 @@ impl PageTable {
     pub fn token(&self) -> usize {
         8usize << 60 | self.root_ppn.0
     }
+
 }
 
-/// Translate&Copy a ptr[u8] array with LENGTH len to a mutable u8 Vec through page table
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
-    let page_table = PageTable::from_token(token); //通过当前stap创建PageTable
-    let mut start = ptr as usize; // 起始地址
-    let end = start + len; // 结束地址
-    let mut v = Vec::new();
+/// Why a user-space pointer couldn't be validated by `copy_from_user`/
+/// `copy_to_user` (or one of the helpers built on them): which virtual
+/// address the walk was at, and whether the page is simply unmapped, not
+/// reachable from user mode, or just missing the permission the access
+/// needed.
+#[derive(Debug, Clone, Copy)]
+pub enum PageFault {
+    /// `va` has no valid PTE at all
+    Unmapped { va: usize },
+    /// `va` maps to a page without the `U` bit set
+    NotUser { va: usize },
+    /// `va` maps to a page missing the `R` (read) or `W` (write) bit the
+    /// access needed
+    NotAccessible { va: usize },
+}
+
+/// Walk `[ptr, ptr + len)` of `page_table`'s address space one page at a
+/// time, checking on every spanned page that the PTE is valid, has `U` set,
+/// and has every bit of `want` (`R` for a read, `W` for a write) set.
+/// Invokes `visit(start_offset, end_offset, ppn)` for each page's portion of
+/// the range instead of collecting anything itself, so `copy_from_user`,
+/// `copy_to_user` and `translated_byte_buffer` can each do their own thing
+/// with the bytes while sharing one checked walk.
+fn walk_user_range(
+    page_table: &PageTable,
+    ptr: usize,
+    len: usize,
+    want: PTEFlags,
+    mut visit: impl FnMut(usize, usize, PhysPageNum),
+) -> Result<(), PageFault> {
+    let mut start = ptr;
+    let end = start + len;
     while start < end {
-        let start_va = VirtAddr::from(start); // 起始虚拟地址
-        let mut vpn = start_va.floor(); // 虚拟页号
-        // page_table.translate(vpn) 通过虚拟页号vpn查找页表项 然后返回页表项中的物理页号
-        let ppn = page_table.translate(vpn).unwrap().ppn();
-        vpn.step(); // 下一个虚拟页号
-        let mut end_va: VirtAddr = vpn.into(); // 当前结束虚拟地址
-        end_va = end_va.min(VirtAddr::from(end));
-        if end_va.page_offset() == 0 {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
-        } else {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
+        let start_va = VirtAddr::from(start);
+        let vpn = start_va.floor();
+        let (_, pte) = page_table
+            .find_pte(vpn)
+            .ok_or(PageFault::Unmapped { va: start })?;
+        if (pte.flags() & PTEFlags::U) == PTEFlags::empty() {
+            return Err(PageFault::NotUser { va: start });
         }
-        start = end_va.into();
+        if (pte.flags() & want) != want {
+            return Err(PageFault::NotAccessible { va: start });
+        }
+        let ppn = pte.ppn();
+        let (start_off, end_off, next_start) = page_span(start, end);
+        visit(start_off, end_off, ppn);
+        start = next_start;
     }
+    Ok(())
+}
+
+/// The in-page byte range `[start, end.min(next page boundary))` covers,
+/// plus the virtual address the following page's span would start at. This
+/// is the arithmetic responsible for a copy that straddles a page boundary
+/// (e.g. a `TimeVal` placed 8 bytes from the end of a page) being split
+/// into two visits by `walk_user_range` instead of one; split out into its
+/// own pure function so it can be unit-tested without a real, mapped
+/// `PageTable`.
+fn page_span(start: usize, end: usize) -> (usize, usize, usize) {
+    let start_va = VirtAddr::from(start);
+    let mut vpn = start_va.floor();
+    vpn.step();
+    let mut end_va: VirtAddr = vpn.into();
+    end_va = end_va.min(VirtAddr::from(end));
+    let start_off = start_va.page_offset();
+    let end_off = if end_va.page_offset() == 0 {
+        PAGE_SIZE
+    } else {
+        end_va.page_offset()
+    };
+    (start_off, end_off, end_va.into())
+}
+
+/// Copy `len` bytes starting at user pointer `ptr` (in the address space
+/// named by `token`) into a freshly-allocated kernel `Vec`, checking `U`+`R`
+/// on every spanned page instead of panicking on a bad pointer.
+pub fn copy_from_user(token: usize, ptr: *const u8, len: usize) -> Result<Vec<u8>, PageFault> {
+    let page_table = PageTable::from_token(token);
+    let mut result = Vec::with_capacity(len);
+    walk_user_range(
+        &page_table,
+        ptr as usize,
+        len,
+        PTEFlags::R,
+        |start_off, end_off, ppn| {
+            result.extend_from_slice(&ppn.get_bytes_array()[start_off..end_off]);
+        },
+    )?;
+    Ok(result)
+}
+
+/// Copy `buf` into user memory at pointer `ptr` (in the address space named
+/// by `token`), checking `U`+`W` on every spanned page instead of panicking
+/// on a bad pointer.
+pub fn copy_to_user(token: usize, ptr: *mut u8, buf: &[u8]) -> Result<(), PageFault> {
+    let page_table = PageTable::from_token(token);
+    let mut written = 0;
+    walk_user_range(
+        &page_table,
+        ptr as usize,
+        buf.len(),
+        PTEFlags::W,
+        |start_off, end_off, ppn| {
+            let copy_len = end_off - start_off;
+            ppn.get_bytes_array()[start_off..end_off]
+                .copy_from_slice(&buf[written..written + copy_len]);
+            written += copy_len;
+        },
+    )?;
+    Ok(())
+}
+
+/// Translate&Copy a ptr[u8] array with LENGTH len to a mutable u8 Vec through page table
+pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
+    let page_table = PageTable::from_token(token);
+    let mut v = Vec::new();
+    walk_user_range(
+        &page_table,
+        ptr as usize,
+        len,
+        PTEFlags::R,
+        |start_off, end_off, ppn| {
+            v.push(&mut ppn.get_bytes_array()[start_off..end_off]);
+        },
+    )
+    .unwrap_or_else(|e| panic!("translated_byte_buffer: invalid user pointer ({:?})", e));
     v
 }
 
 
 /// Translate&Copy a ptr[u8] array end with `\0` to a `String` Vec through page table
 pub fn translated_str(token: usize, ptr: *const u8) -> String {
-    let page_table = PageTable::from_token(token); //通过satp获取page_table
     let mut string = String::new();
     let mut va = ptr as usize;
     loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
+        let byte = copy_from_user(token, va as *const u8, 1)
+            .unwrap_or_else(|e| panic!("translated_str: invalid user pointer ({:?})", e));
+        let ch = byte[0];
         if ch == 0 {
             break;
         }
@@ -225,19 +551,31 @@ pub fn translated_str(token: usize, ptr: *const u8) -> String {
 /// Translate a ptr[u8] array through page table and return a reference of T
 pub fn translated_ref<T>(token: usize, ptr: *const T) -> &'static T {
     let page_table = PageTable::from_token(token);
+    walk_user_range(&page_table, ptr as usize, core::mem::size_of::<T>(), PTEFlags::R, |_, _, _| {})
+        .unwrap_or_else(|e| panic!("translated_ref: invalid user pointer ({:?})", e));
     page_table
         .translate_va(VirtAddr::from(ptr as usize))
         .unwrap()
         .get_ref()
 }
+/// Serialize `*value` and copy it into user memory at pointer `ptr`, via
+/// [`copy_to_user`] so a struct straddling a page boundary is written
+/// segment-by-segment instead of assuming its second half follows the first
+/// in physical memory.
+pub fn copy_val_to_user<T: Copy>(token: usize, ptr: *mut T, value: &T) -> Result<(), PageFault> {
+    let bytes = unsafe {
+        core::slice::from_raw_parts((value as *const T).cast::<u8>(), core::mem::size_of::<T>())
+    };
+    copy_to_user(token, ptr.cast::<u8>(), bytes)
+}
+
 /// Translate a ptr[u8] array through page table and return a mutable reference of T
 pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
     let page_table = PageTable::from_token(token);
     let va = ptr as usize;
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_mut()
+    walk_user_range(&page_table, va, core::mem::size_of::<T>(), PTEFlags::W, |_, _, _| {})
+        .unwrap_or_else(|e| panic!("translated_refmut: invalid user pointer ({:?})", e));
+    page_table.translate_va(VirtAddr::from(va)).unwrap().get_mut()
 }
 
 /// Get the physical address from the page table
@@ -317,5 +655,42 @@ impl Iterator for UserBufferIterator {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_val_straddling_a_page_boundary_splits_into_two_visits() {
+        // a `TimeVal` (two `usize` fields, 16 bytes on a 64-bit target)
+        // placed 8 bytes before the end of a page straddles into the next
+        // one instead of fitting in a single page's span
+        let ptr = PAGE_SIZE - 8;
+        let len = 16;
+        let end = ptr + len;
+
+        let (start_off, end_off, next_start) = page_span(ptr, end);
+        assert_eq!(start_off, PAGE_SIZE - 8);
+        assert_eq!(end_off, PAGE_SIZE);
+        assert_eq!(next_start, PAGE_SIZE);
+
+        let (start_off2, end_off2, next_start2) = page_span(next_start, end);
+        assert_eq!(start_off2, 0);
+        assert_eq!(end_off2, 8);
+        assert_eq!(next_start2, end);
+
+        // together the two visits cover exactly the 16 requested bytes
+        assert_eq!((end_off - start_off) + (end_off2 - start_off2), len);
+    }
+
+    #[test]
+    fn copy_fully_inside_one_page_does_not_split() {
+        let ptr = 0;
+        let len = 16;
+        let (start_off, end_off, next_start) = page_span(ptr, ptr + len);
+        assert_eq!((start_off, end_off), (0, len));
+        assert_eq!(next_start, len);
+    }
+}
+
 
 
@@ -1,12 +1,12 @@
 //! Process management syscalls
 use crate::{
-    config::MAX_SYSCALL_NUM, mm::get_phyical_address, task::{
-        change_program_brk, current_task_mmap, current_task_munmap, current_task_status, current_task_syscall_times, current_user_token, exit_current_and_run_next, first_dispatched_time, suspend_current_and_run_next, TaskStatus
-    }, timer::{get_time_ms, get_time_us} 
+    config::MAX_SYSCALL_NUM, mm::{copy_val_to_user, translated_str}, task::{
+        change_program_brk, current_task_kernel_time, current_task_mmap, current_task_munmap, current_task_set_priority, current_task_status, current_task_syscall_times, current_task_user_time, current_user_token, exit_current_and_run_next, fork, first_dispatched_time, getpid, gettid, exec as task_exec, suspend_current_and_run_next, thread_create, waitpid, waittid, TaskStatus
+    }, timer::{get_time_ms, get_time_us}
 };
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 /// Time value
 pub struct TimeVal {
     /// Seconds since Unix epoch
@@ -17,6 +17,7 @@ pub struct TimeVal {
 
 /// Task information
 #[allow(dead_code)]
+#[derive(Clone, Copy)]
 pub struct TaskInfo {
     /// Task status in it's life cycle
     status: TaskStatus,
@@ -24,12 +25,31 @@ pub struct TaskInfo {
     syscall_times: [u32; MAX_SYSCALL_NUM],
     /// Total running time of task
     time: usize,
+    /// Total microseconds spent running in user mode
+    utime: usize,
+    /// Total microseconds spent running in kernel mode
+    stime: usize,
+}
+
+/// Per-task CPU time, in microseconds, split by privilege mode
+#[repr(C)]
+#[derive(Debug)]
+pub struct CpuTime {
+    /// Total microseconds spent running in user mode
+    pub user_time: usize,
+    /// Total microseconds spent running in kernel mode
+    pub kernel_time: usize,
 }
 
 /// task exits and submit an exit code
-pub fn sys_exit(_exit_code: i32) -> ! {
+pub fn sys_exit(exit_code: i32) -> ! {
     trace!("kernel: sys_exit");
-    exit_current_and_run_next();
+    exit_current_and_run_next(exit_code);
+    // `exit_current_and_run_next` never returns for a thread that just
+    // exited; reaching this line is a kernel bug, not a user error, so print
+    // a trace before panicking the same way the unsupported-syscall arm of
+    // `syscall()` does.
+    super::stack_trace::print_stack_trace();
     panic!("Unreachable in sys_exit!");
 }
 
@@ -45,18 +65,16 @@ pub fn sys_yield() -> isize {
 /// HINT: What if [`TimeVal`] is splitted by two pages ?
 pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> isize {
     trace!("kernel: sys_get_time");
-    // 获取当前地址空间的页表
     let token = current_user_token();
-    let physical_address = get_phyical_address(token, ts as usize);
     let time = get_time_us();
-
-    unsafe {
-        *(physical_address as *mut TimeVal) = TimeVal {
-            sec: time / 1_000_000,
-            usec: time % 1_000_000,
-        };
+    let time_val = TimeVal {
+        sec: time / 1_000_000,
+        usec: time % 1_000_000,
+    };
+    match copy_val_to_user(token, ts, &time_val) {
+        Ok(()) => 0,
+        Err(_) => -1,
     }
-    0
 }
 
 /// YOUR JOB: Finish sys_task_info to pass testcases
@@ -65,14 +83,32 @@ pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> isize {
 pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
     trace!("kernel: sys_task_info");
     let token = current_user_token();
-    let physical_address = get_phyical_address(token, ti as usize);
-    let ptr = physical_address as *mut TaskInfo;
-    unsafe {
-        (*ptr).status = current_task_status();
-        (*ptr).syscall_times = current_task_syscall_times();
-        (*ptr).time = get_time_ms() - first_dispatched_time();
+    let task_info = TaskInfo {
+        status: current_task_status(),
+        syscall_times: current_task_syscall_times(),
+        time: get_time_ms() - first_dispatched_time(),
+        utime: current_task_user_time(),
+        stime: current_task_kernel_time(),
+    };
+    match copy_val_to_user(token, ti, &task_info) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// get the current task's CPU time split into user time and kernel time,
+/// in microseconds, so tools can compute per-task CPU utilization
+pub fn sys_get_cpu_time(ct: *mut CpuTime) -> isize {
+    trace!("kernel: sys_get_cpu_time");
+    let token = current_user_token();
+    let cpu_time = CpuTime {
+        user_time: current_task_user_time(),
+        kernel_time: current_task_kernel_time(),
+    };
+    match copy_val_to_user(token, ct, &cpu_time) {
+        Ok(()) => 0,
+        Err(_) => -1,
     }
-    0
 }
 
 /// YOUR JOB: Implement mmap.
@@ -87,6 +123,13 @@ pub fn sys_munmap(start: usize, len: usize) -> isize {
     current_task_munmap(start, len)
 }
 
+/// set the scheduling priority of the current thread; returns -1 if `prio`
+/// is below 2
+pub fn sys_set_priority(prio: isize) -> isize {
+    trace!("kernel: sys_set_priority");
+    current_task_set_priority(prio)
+}
+
 /// change data segment size
 pub fn sys_sbrk(size: i32) -> isize {
     trace!("kernel: sys_sbrk");
@@ -96,3 +139,61 @@ pub fn sys_sbrk(size: i32) -> isize {
         -1
     }
 }
+
+/// create a new thread in the current process, running `entry(arg)`
+pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
+    trace!("kernel: sys_thread_create");
+    thread_create(entry, arg)
+}
+
+/// get the thread id of the current thread
+pub fn sys_gettid() -> isize {
+    trace!("kernel: sys_gettid");
+    gettid()
+}
+
+/// wait for thread `tid` of the current process to exit, reaping it and
+/// returning its exit code; blocks the caller until `tid` exits, or
+/// returns -1 if `tid` is not a thread of this process
+pub fn sys_waittid(tid: usize) -> isize {
+    trace!("kernel: sys_waittid");
+    waittid(tid)
+}
+
+/// get the pid of the current process
+pub fn sys_getpid() -> isize {
+    trace!("kernel: sys_getpid");
+    getpid() as isize
+}
+
+/// fork the current process, returning the child's pid to the parent and 0
+/// to the child
+pub fn sys_fork() -> isize {
+    trace!("kernel: sys_fork");
+    fork()
+}
+
+/// replace the current process's address space with the ELF named by the
+/// NUL-terminated path at `path`; -1 if no such app exists
+pub fn sys_exec(path: *const u8) -> isize {
+    trace!("kernel: sys_exec");
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    task_exec(&path)
+}
+
+/// wait for a child process to become a zombie and reap it, writing its
+/// exit code out to `exit_code_ptr`; `pid == -1` matches any child.
+/// Returns -1 if no matching child exists, -2 if the matching child(ren)
+/// are still running, or the reaped child's pid
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+    trace!("kernel: sys_waitpid");
+    let (result, exit_code) = waitpid(pid);
+    if result >= 0 {
+        let token = current_user_token();
+        if copy_val_to_user(token, exit_code_ptr, &exit_code).is_err() {
+            return -1;
+        }
+    }
+    result
+}
@@ -0,0 +1,27 @@
+//! Kernel stack backtrace, walked via the saved frame-pointer chain instead
+//! of DWARF unwind tables. Every call frame (when built with
+//! `-C force-frame-pointers=yes`) stores the caller's return address at
+//! `*(fp - 1)` and the caller's own frame pointer at `*(fp - 2)`, so
+//! following `fp` upward recovers the chain of return addresses.
+
+/// Print every return address reachable by walking the frame-pointer chain
+/// from the current `fp`, stopping at a null, misaligned, or
+/// non-ascending frame pointer so a corrupted stack can't turn a backtrace
+/// into a second fault.
+pub fn print_stack_trace() {
+    let mut fp: usize;
+    unsafe {
+        core::arch::asm!("mv {}, fp", out(reg) fp);
+    }
+    println!("Begin stack trace");
+    while fp != 0 && fp % core::mem::size_of::<usize>() == 0 {
+        let ra = unsafe { *(fp as *const usize).offset(-1) };
+        let saved_fp = unsafe { *(fp as *const usize).offset(-2) };
+        println!("0x{:016x}", ra);
+        if saved_fp <= fp {
+            break;
+        }
+        fp = saved_fp;
+    }
+    println!("End stack trace");
+}
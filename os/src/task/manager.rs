@@ -7,12 +7,31 @@ use lazy_static::*;
 
 const BIG_STRIDE: usize = 0x10000000;
 
+/// Stride increment for one scheduling pass at `priority`; every priority is
+/// enforced to be >= 2 (see `TaskControlBlock::set_priority`), so this is
+/// always at most `BIG_STRIDE / 2`. Split out as a free function so unit
+/// tests can exercise the stride algorithm without a real `TaskControlBlock`.
+fn stride_increment(priority: isize) -> usize {
+    BIG_STRIDE / priority as usize
+}
+
+/// Whether stride `a` is logically behind stride `b`, using a
+/// wrapping-aware comparison instead of plain `<`: as long as every
+/// priority is at least 2, `max(stride) - min(stride)` never exceeds
+/// `BIG_STRIDE / 2` at any scheduling point, so `a.wrapping_sub(b) as isize`
+/// reliably tells which of two strides is behind even across a wraparound.
+fn stride_is_earlier(a: usize, b: usize) -> bool {
+    (a.wrapping_sub(b) as isize) < 0
+}
+
 ///A array of `TaskControlBlock` that is thread-safe
 pub struct TaskManager {
     ready_queue: VecDeque<Arc<TaskControlBlock>>,
 }
 
-/// A simple FIFO scheduler.
+/// Holds every `Ready` task. `fetch` serves plain FIFO order; `fetch_min_stride_task`
+/// implements stride scheduling, handing out the task with the smallest
+/// `stride` so CPU share is proportional to `priority` instead of flat round-robin.
 impl TaskManager {
     ///Creat an empty TaskManager
     pub fn new() -> Self {
@@ -20,17 +39,22 @@ impl TaskManager {
             ready_queue: VecDeque::new(),
         }
     }
-    /// Add process back to ready queue
+    /// Add a thread back to the ready queue
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
         self.ready_queue.push_back(task);
     }
-    /// Take a process out of the ready queue
+    /// Take a thread out of the ready queue
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
         self.ready_queue.pop_front()
     }
 
-    /// Take a process out of the ready queue with stride scheduling
+    /// Take a thread out of the ready queue with stride scheduling; see
+    /// [`stride_is_earlier`] for why the minimum is found with a
+    /// wraparound-aware comparison instead of plain `<`.
     pub fn fetch_min_stride_task(&mut self) -> Option<Arc<TaskControlBlock>> {
+        if self.ready_queue.is_empty() {
+            return None;
+        }
         //default first task is min_stride_task
         let mut min_tcb = self.ready_queue[0].clone();
         let min_stride_task = min_tcb.inner_exclusive_access();
@@ -40,7 +64,7 @@ impl TaskManager {
         //find min_stride_task
         for tcb in self.ready_queue.iter() {
             let task = tcb.inner_exclusive_access();
-            if task.stride < min_stride {
+            if stride_is_earlier(task.stride, min_stride) {
                 min_tcb = tcb.clone();
                 min_stride = task.stride;
             }
@@ -52,8 +76,8 @@ impl TaskManager {
         }
 
         let mut min_stride_task = min_tcb.inner_exclusive_access();
-        // update stride
-        min_stride_task.stride = min_stride_task.stride + BIG_STRIDE / min_stride_task.priority as usize;
+        min_stride_task.stride =
+            min_stride_task.stride.wrapping_add(stride_increment(min_stride_task.priority));
 
         drop(min_stride_task);
         Some(min_tcb)
@@ -66,13 +90,13 @@ lazy_static! {
         unsafe { UPSafeCell::new(TaskManager::new()) };
 }
 
-/// Add process to ready queue
+/// Add a thread to the ready queue
 pub fn add_task(task: Arc<TaskControlBlock>) {
     //trace!("kernel: TaskManager::add_task");
     TASK_MANAGER.exclusive_access().add(task);
 }
 
-/// Take a process out of the ready queue
+/// Take a thread out of the ready queue
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
     //trace!("kernel: TaskManager::fetch_task");
     TASK_MANAGER.exclusive_access().fetch()
@@ -82,3 +106,51 @@ pub fn fetch_min_task() -> Option<Arc<TaskControlBlock>> {
     //trace!("kernel: TaskManager::fetch_task");
     TASK_MANAGER.exclusive_access().fetch_min_stride_task()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    /// Replay `fetch_min_stride_task`'s selection rule over plain
+    /// `(stride, priority)` pairs for `rounds` scheduling decisions, without
+    /// needing real `TaskControlBlock`s, returning how many times each
+    /// index was picked.
+    fn run_rounds(priorities: &[isize], start_strides: &[usize], rounds: usize) -> Vec<usize> {
+        let mut strides = start_strides.to_vec();
+        let mut picks = vec![0usize; priorities.len()];
+        for _ in 0..rounds {
+            let mut min_idx = 0;
+            for i in 1..strides.len() {
+                if stride_is_earlier(strides[i], strides[min_idx]) {
+                    min_idx = i;
+                }
+            }
+            picks[min_idx] += 1;
+            strides[min_idx] = strides[min_idx].wrapping_add(stride_increment(priorities[min_idx]));
+        }
+        picks
+    }
+
+    #[test]
+    fn stride_scheduling_does_not_starve_any_priority() {
+        let priorities = [2, 4, 8, 16, 32];
+        let picks = run_rounds(&priorities, &[0; 5], 50_000);
+        for (i, &count) in picks.iter().enumerate() {
+            assert!(count > 0, "priority {} was never scheduled in 50,000 rounds", priorities[i]);
+        }
+    }
+
+    #[test]
+    fn stride_scheduling_survives_counter_wraparound() {
+        // every task starts just below `usize::MAX`, so the first few
+        // rounds immediately wrap the stride counter around; starvation
+        // here would mean `stride_is_earlier` got the wraparound wrong
+        let priorities = [2, 3, 5, 16];
+        let start = usize::MAX - BIG_STRIDE;
+        let picks = run_rounds(&priorities, &[start; 4], 20_000);
+        for (i, &count) in picks.iter().enumerate() {
+            assert!(count > 0, "priority {} was starved across a stride wraparound", priorities[i]);
+        }
+    }
+}
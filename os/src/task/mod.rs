@@ -1,256 +1,445 @@
 //! Task management implementation
 //!
-//! Everything about task management, like starting and switching tasks is
+//! Everything about task management, like launching and switching tasks, is
 //! implemented here.
 //!
-//! A single global instance of [`TaskManager`] called `TASK_MANAGER` controls
-//! all the tasks in the operating system.
+//! A [`ProcessControlBlock`] owns the resources its threads share (address
+//! space, fd table, parent/children links); each schedulable thread is a
+//! [`TaskControlBlock`] with its own `tid`, user stack, trap-context page and
+//! `task_cx`, allocated on demand by [`thread::ThreadUserRes`]. A single
+//! global instance of [`manager::TaskManager`] called `TASK_MANAGER` holds
+//! every `Ready` thread, while a single global instance of
+//! [`processor::Processor`] called `PROCESSOR` tracks which thread (if any)
+//! is currently running on the CPU. Scheduling decisions move threads
+//! between the two: `run_tasks` fetches from the manager into the
+//! processor, and `suspend_current_and_run_next`/`exit_current_and_run_next`
+//! push the current thread back out.
 //!
 //! Be careful when you see `__switch` ASM function in `switch.S`. Control flow around this function
 //! might not be what you expect.
 
 mod context;
+mod manager;
+mod pid;
+mod process;
+mod processor;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
+mod thread;
 
-use crate::config::{MAX_APP_NUM, MAX_SYSCALL_NUM};
-use crate::loader::{get_num_app, init_app_cx};
+use crate::config::{MAX_SYSCALL_NUM, TIME_SLICE};
+use crate::loader::get_app_data_by_name;
+use crate::mm::VirtAddr;
 use crate::sync::UPSafeCell;
+use crate::syscall::SyscallInfo;
 use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
+use manager::fetch_min_task;
 use switch::__switch;
-pub use task::{TaskControlBlock, TaskStatus, TaskInfo};
 
 pub use context::TaskContext;
+pub use manager::add_task;
+pub use pid::{kstack_alloc, pid_alloc, KernelStack, PidHandle};
+pub use process::ProcessControlBlock;
+pub use processor::{
+    current_task, current_trap_cx, current_user_token, run_tasks, schedule, take_current_task,
+};
+pub use task::{TaskControlBlock, TaskInfo, TaskStatus};
+pub use thread::ThreadUserRes;
 
-/// The task manager, where all the tasks are managed.
-///
-/// Functions implemented on `TaskManager` deals with all task state transitions
-/// and task context switching. For convenience, you can find wrappers around it
-/// in the module level.
-///
-/// Most of `TaskManager` are hidden behind the field `inner`, to defer
-/// borrowing checks to runtime. You can see examples on how to use `inner` in
-/// existing functions on `TaskManager`.
-pub struct TaskManager {
-    /// total number of tasks
-    num_app: usize,
-    /// use inner value to get mutable access
-    inner: UPSafeCell<TaskManagerInner>,
+/// The process currently running on the CPU
+fn current_process() -> Arc<ProcessControlBlock> {
+    current_task().unwrap().process()
 }
 
-/// Inner of Task Manager
-pub struct TaskManagerInner {
-    /// task list
-    tasks: [TaskControlBlock; MAX_APP_NUM],
-    /// id of current `Running` task
-    current_task: usize,
+/// Suspend the current `Running` thread, push it back onto the ready queue
+/// as `Ready`, and return control to the idle scheduling loop.
+pub fn suspend_current_and_run_next() {
+    // There must be an application running.
+    let task = take_current_task().unwrap();
+
+    // ---- access current TCB exclusively
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    // Change status to Ready
+    task_inner.task_status = TaskStatus::Ready;
+    let now = crate::timer::get_time_us();
+    task_inner.task_info.kernel_time += now - task_inner.task_info.last_switch_time;
+    task_inner.task_info.last_switch_time = now;
+    drop(task_inner);
+    // ---- release current TCB
+
+    // push back to ready queue.
+    add_task(task);
+    // jump to scheduling cycle
+    schedule(task_cx_ptr);
 }
 
-lazy_static! {
-    /// Global variable: TASK_MANAGER
-    pub static ref TASK_MANAGER: TaskManager = {
-        let num_app = get_num_app();
-        let mut tasks: [TaskControlBlock; MAX_APP_NUM]= core::array::from_fn(|_| {
-            TaskControlBlock {
-                task_cx: TaskContext::zero_init(),
-                task_status: TaskStatus::UnInit,
-                task_info: TaskInfo::default(),
+/// Exit the current `Running` thread, recording its exit code so a later
+/// `sys_waittid` can reap it. If it is the process's main thread (`tid == 0`)
+/// the whole process exits with it: every other thread's stack/trap-context
+/// page is reclaimed, its children are re-parented to `INITPROC`, and the
+/// process becomes a `Zombie` for a future `waitpid`.
+pub fn exit_current_and_run_next(exit_code: i32) {
+    // take from Processor
+    let task = take_current_task().unwrap();
+    let process = task.process();
+    let tid = task.tid();
+
+    // **** access current TCB exclusively
+    let mut inner = task.inner_exclusive_access();
+    // Change status to Zombie
+    inner.task_status = TaskStatus::Zombie;
+    // Record exit code
+    inner.exit_code = exit_code;
+    // this thread's user stack/trap-context page are no longer needed;
+    // reclaim them now rather than waiting for `sys_waittid`
+    inner.res = None;
+    let now = crate::timer::get_time_us();
+    inner.task_info.kernel_time += now - inner.task_info.last_switch_time;
+    inner.task_info.last_switch_time = now;
+    drop(inner);
+    // **** release current TCB
+    // wake any sibling thread parked in `waittid(tid)` on this one
+    wake(tid);
+    // drop task manually to maintain rc correctly
+    drop(task);
+
+    if tid == 0 {
+        let mut process_inner = process.inner_exclusive_access();
+        process_inner.is_zombie = true;
+        process_inner.exit_code = exit_code;
+        // do not move children to its parent but under initproc
+        // ++++++ access initproc PCB exclusively
+        {
+            let mut initproc_inner = INITPROC.inner_exclusive_access();
+            for child in process_inner.children.iter() {
+                child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
+                initproc_inner.children.push(child.clone());
             }
-        });
-        for (i, task) in tasks.iter_mut().enumerate() {
-            task.task_cx = TaskContext::goto_restore(init_app_cx(i));
-            task.task_status = TaskStatus::Ready;
         }
-        TaskManager {
-            num_app,
-            inner: unsafe {
-                UPSafeCell::new(TaskManagerInner {
-                    tasks,
-                    current_task: 0,
-                })
-            },
-        }
-    };
-}
+        // ++++++ release initproc PCB
+        process_inner.children.clear();
 
-impl TaskManager {
-    /// Run the first task in task list.
-    ///
-    /// Generally, the first task in task list is an idle task (we call it zero process later).
-    /// But in ch3, we load apps statically, so the first task is a real app.
-    fn run_first_task(&self) -> ! {
-        let mut inner = self.inner.exclusive_access();
-        let task0 = &mut inner.tasks[0];
-        task0.task_status = TaskStatus::Running;
-        task0.task_info.set_timestamp_is_first_dispatched();
-        let next_task_cx_ptr = &task0.task_cx as *const TaskContext;
-        drop(inner);
-        let mut _unused = TaskContext::zero_init();
-        // before this, we should drop local variables that must be dropped manually
-        unsafe {
-            __switch(&mut _unused as *mut TaskContext, next_task_cx_ptr);
+        // reclaim every other thread's stack/trap-context page; collect
+        // them first and release `process_inner` before dropping, since
+        // `ThreadUserRes::drop` needs to reacquire it
+        let mut recycled_res = Vec::new();
+        for task in process_inner.tasks.iter().flatten() {
+            let mut task_inner = task.inner_exclusive_access();
+            if let Some(res) = task_inner.res.take() {
+                recycled_res.push(res);
+            }
         }
-        panic!("unreachable in run_first_task!");
+        drop(process_inner);
+        recycled_res.clear();
     }
+    drop(process);
+    // we do not have to save task context
+    let mut _unused = TaskContext::zero_init();
+    schedule(&mut _unused as *mut _);
+}
 
-    /// Change the status of current `Running` task into `Ready`.
-    fn mark_current_suspended(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        inner.tasks[current].task_status = TaskStatus::Ready;
-    }
+lazy_static! {
+    /// Global process that init user shell, the root of the process tree
+    /// every exiting process's orphaned children are re-parented to.
+    pub static ref INITPROC: Arc<ProcessControlBlock> = ProcessControlBlock::new(
+        get_app_data_by_name("initproc").unwrap()
+    );
+}
 
-    /// Change the status of current `Running` task into `Exited`.
-    fn mark_current_exited(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        inner.tasks[current].task_status = TaskStatus::Exited;
-    }
+/// Add `INITPROC`'s main thread to the ready queue so `run_tasks` has
+/// something to run.
+pub fn add_initproc() {
+    let main_thread = INITPROC.inner_exclusive_access().tasks[0]
+        .as_ref()
+        .unwrap()
+        .clone();
+    add_task(main_thread);
+}
 
-    /// Find next task to run and return task id.
-    ///
-    /// In this case, we only return the first `Ready` task in task list.
-    fn find_next_task(&self) -> Option<usize> {
-        let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        (current + 1..current + self.num_app + 1)
-            .map(|id| id % self.num_app)
-            .find(|id| inner.tasks[*id].task_status == TaskStatus::Ready)
+/// Meant to be driven by the supervisor timer interrupt, once per tick; in
+/// the absence of that interrupt path, [`crate::syscall::syscall`] calls
+/// this once per syscall instead, so a thread that only ever traps in
+/// through `ecall` still eventually has its slice charged and is still
+/// forced to give up the CPU if it never calls `sys_yield` on its own.
+///
+/// Decrements the current thread's remaining time-slice and, once it is
+/// exhausted, forces the thread to give up the CPU.
+pub fn on_tick() {
+    let Some(task) = current_task() else {
+        return;
+    };
+    let mut inner = task.inner_exclusive_access();
+    if inner.task_info.remaining_slice > 0 {
+        inner.task_info.remaining_slice -= 1;
     }
-
-    /// Switch current `Running` task to the task we have found,
-    /// or there is no `Ready` task and we can exit with all applications completed
-    fn run_next_task(&self) {
-        if let Some(next) = self.find_next_task() {
-            let mut inner = self.inner.exclusive_access();
-            let current = inner.current_task;
-            inner.tasks[next].task_status = TaskStatus::Running;
-            inner.current_task = next;
-            let current_task_cx_ptr = &mut inner.tasks[current].task_cx as *mut TaskContext;
-            let next_task_cx_ptr = &inner.tasks[next].task_cx as *const TaskContext;
-            drop(inner);
-            // before this, we should drop local variables that must be dropped manually
-            unsafe {
-                __switch(current_task_cx_ptr, next_task_cx_ptr);
-            }
-            // go back to user mode
-        } else {
-            panic!("All applications completed!");
-        }
+    let expired = inner.task_info.remaining_slice == 0;
+    if expired {
+        inner.task_info.remaining_slice = TIME_SLICE;
     }
-
-    /// Get the task status of the current task
-    fn get_current_task_status(&self) -> TaskStatus {
-        let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        inner.tasks[current].task_status
+    drop(inner);
+    if expired {
+        suspend_current_and_run_next();
     }
+}
 
-    /// Get the task syscall times of the current task
-    /// the key is the syscall id, the value is the times
-    fn get_current_task_syscall_times(&self) -> BTreeMap<usize, usize> {
-        let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        inner.tasks[current].task_info.syscall_times.clone()
-    }
+/// Get the task status of the current thread
+pub fn current_task_status() -> TaskStatus {
+    current_task().unwrap().get_task_status()
+}
 
-    /// Get the task syscall list of the current task
-    // fn get_current_task_syscall_list(&self) -> Vec<SyscallInfo> {
-    //     let inner = self.inner.exclusive_access();
-    //     let current = inner.current_task;
-    //     inner.tasks[current].task_info.syscall_list.clone()
-    // }
-
-    /// Add syscall call times to current task;
-    fn add_current_task_syscall_times(&self, syscall_id: usize) {
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        let task_info = &mut inner.tasks[current].task_info;
-        *task_info.syscall_times.entry(syscall_id).or_insert(0) += 1;
-    }
+/// Get the task syscall times of the current thread
+pub fn current_task_syscall_times() -> [u32; MAX_SYSCALL_NUM] {
+    current_task().unwrap().get_task_syscall_times()
+}
+
+/// Add syscall times to the current thread
+pub fn add_current_task_syscall_times(syscall_id: usize) {
+    current_task().unwrap().add_task_syscall_times(syscall_id);
+}
+
+/// Add syscall info to the current thread
+pub fn add_current_task_syscall_info(syscall_info: SyscallInfo) {
+    current_task().unwrap().add_task_syscall_info(syscall_info);
+}
+
+/// Get the first dispatched time of the current thread
+pub fn first_dispatched_time() -> usize {
+    current_task().unwrap().get_first_dispatched_time()
+}
+
+/// Charge elapsed time to the current thread's `user_time`. A syscall trap
+/// is one concrete case of "a trap from user mode was taken"; [`crate::syscall::syscall`]
+/// calls this first, before dispatching, so every syscall's time in user
+/// mode up to the `ecall` is charged correctly.
+pub fn current_task_charge_user_time() {
+    current_task().unwrap().charge_user_time();
+}
+
+/// Charge elapsed time to the current thread's `kernel_time`. Called by
+/// [`crate::syscall::syscall`] right before it returns control to user mode.
+pub fn current_task_charge_kernel_time() {
+    current_task().unwrap().charge_kernel_time();
+}
 
-    /// Add syscall info to current task;
-    // fn add_current_task_syscall_info(&self, syscall_info: SyscallInfo) {
-    //     let mut inner = self.inner.exclusive_access();
-    //     let current = inner.current_task;
-    //     let task_info = &mut inner.tasks[current].task_info;
-    //     task_info.syscall_list.push(syscall_info);
-    // }
-
-    /// Get the first dispatched time of the current task
-    fn get_current_task_first_dispatched_time(&self) -> usize {
-        let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        inner.tasks[current].task_info.first_dispatched_time
+/// Total microseconds the current thread has spent running in user mode
+pub fn current_task_user_time() -> usize {
+    current_task().unwrap().get_user_time()
+}
+
+/// Total microseconds the current thread has spent running in kernel mode
+pub fn current_task_kernel_time() -> usize {
+    current_task().unwrap().get_kernel_time()
+}
+
+/// Set the scheduling priority of the current thread; `prio` must be at
+/// least 2 so every task's stride `pass` stays within `BIG_STRIDE / 2`
+pub fn current_task_set_priority(prio: isize) -> isize {
+    if prio < 2 {
+        return -1;
     }
+    current_task().unwrap().set_priority(prio);
+    prio
+}
 
+/// Handle a store page fault at user virtual address `va` in the current
+/// process: if the faulting page is copy-on-write (see
+/// [`ProcessControlBlock::fork`]), materialize a private copy (or reuse the
+/// shared frame in place if nothing else still holds it) and let the
+/// trap handler resume the faulting instruction. Returns `false` for a real
+/// permission fault the trap handler must still report some other way.
+pub fn current_task_handle_cow_fault(va: usize) -> bool {
+    current_process()
+        .inner_exclusive_access()
+        .memory_set
+        .handle_cow_fault(VirtAddr::from(va))
+}
 
+/// Map a region of the current process's address space
+pub fn current_task_mmap(start: usize, len: usize, port: usize) -> isize {
+    current_process().alloc_memory(start, len, port)
 }
 
-/// Run the first task in task list.
-pub fn run_first_task() {
-    TASK_MANAGER.run_first_task();
+/// Unmap a region of the current process's address space
+pub fn current_task_munmap(start: usize, len: usize) -> isize {
+    current_process().dealloc(start, len)
 }
 
-/// Switch current `Running` task to the task we have found,
-/// or there is no `Ready` task and we can exit with all applications completed
-fn run_next_task() {
-    TASK_MANAGER.run_next_task();
+/// Change the program break of the current process
+pub fn change_program_brk(size: i32) -> Option<usize> {
+    current_process().change_program_brk(size)
 }
 
-/// Change the status of current `Running` task into `Ready`.
-fn mark_current_suspended() {
-    TASK_MANAGER.mark_current_suspended();
+/// Create a new thread in the current process running `entry(arg)`, mapping
+/// its stack and trap-context page via [`ThreadUserRes`] and pushing it onto
+/// the ready queue. Returns the new thread's tid.
+pub fn thread_create(entry: usize, arg: usize) -> isize {
+    let task = current_task().unwrap();
+    let process = task.process();
+    let ustack_base = task.ustack_base();
+    let new_task = Arc::new(TaskControlBlock::new(Arc::clone(&process), ustack_base, true));
+    let new_tid = new_task.tid();
+
+    let mut process_inner = process.inner_exclusive_access();
+    // keep `tasks`'s index in step with `tid`
+    while process_inner.tasks.len() <= new_tid {
+        process_inner.tasks.push(None);
+    }
+    process_inner.tasks[new_tid] = Some(Arc::clone(&new_task));
+    drop(process_inner);
+
+    let ustack_top = new_task.inner_exclusive_access().res.as_ref().unwrap().ustack_top();
+    new_task.prepare_init_context(entry, ustack_top);
+    // the new thread's a0 carries `arg`, mirroring how `app_init_context`
+    // leaves argc/argv in a0/a1 for a freshly exec'd process
+    new_task.inner_exclusive_access().get_trap_cx().x[10] = arg;
+
+    add_task(Arc::clone(&new_task));
+    new_tid as isize
 }
 
-/// Change the status of current `Running` task into `Exited`.
-fn mark_current_exited() {
-    TASK_MANAGER.mark_current_exited();
+/// Get the tid of the current thread
+pub fn gettid() -> isize {
+    current_task().unwrap().tid() as isize
 }
 
-/// Suspend the current 'Running' task and run the next task in task list.
-pub fn suspend_current_and_run_next() {
-    mark_current_suspended();
-    run_next_task();
+/// Get the pid of the current process
+pub fn getpid() -> usize {
+    current_process().getpid()
 }
 
-/// Exit the current 'Running' task and run the next task in task list.
-pub fn exit_current_and_run_next() {
-    mark_current_exited();
-    run_next_task();
+/// Fork the current process: share its address space copy-on-write and copy
+/// its fd table into a new child via [`ProcessControlBlock::fork`], zero the child's `a0` so
+/// it can tell itself apart from the parent, and push its main thread onto
+/// the ready queue. Returns the child's pid, which becomes the parent's
+/// syscall return value.
+pub fn fork() -> isize {
+    let task = current_task().unwrap();
+    let process = task.process();
+    let child = process.fork(&task);
+    let child_pid = child.getpid();
+    let child_task = child.inner_exclusive_access().tasks[0]
+        .as_ref()
+        .unwrap()
+        .clone();
+    // the parent receives the child pid (below); the child must see 0
+    child_task.inner_exclusive_access().get_trap_cx().x[10] = 0;
+    add_task(child_task);
+    child_pid as isize
 }
 
-/// Get the task status of the current task
-pub fn current_task_status() -> TaskStatus {
-    TASK_MANAGER.get_current_task_status()
+/// Replace the current process's address space and main thread's trap
+/// context with a freshly loaded ELF named `path`, discarding every other
+/// thread. Returns -1 if no app named `path` exists.
+pub fn exec(path: &str) -> isize {
+    let Some(elf_data) = crate::loader::get_app_data_by_name(path) else {
+        return -1;
+    };
+    current_process().exec(elf_data);
+    0
 }
 
-/// Get the task syscall times of the current task
-pub fn current_task_syscall_times() -> [u32; MAX_SYSCALL_NUM] {
-    let syscall_times_map = TASK_MANAGER.get_current_task_syscall_times();
-    let mut syscall_times = [0; MAX_SYSCALL_NUM];
+/// Wait for a child of the current process to become a zombie and reap it;
+/// `pid == -1` matches any child. Returns `(-1, _)` if no matching child
+/// exists, `(-2, _)` if the matching child(ren) are still running, or
+/// `(reaped_pid, exit_code)` once one is reaped.
+pub fn waitpid(pid: isize) -> (isize, i32) {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if !inner
+        .children
+        .iter()
+        .any(|p| pid == -1 || pid as usize == p.getpid())
+    {
+        return (-1, 0);
+    }
+    let found = inner.children.iter().enumerate().find(|(_, p)| {
+        (pid == -1 || pid as usize == p.getpid()) && p.inner_exclusive_access().is_zombie()
+    });
+    let Some((idx, _)) = found else {
+        return (-2, 0);
+    };
+    let child = inner.children.remove(idx);
+    // every thread of the child was reclaimed by `exit_current_and_run_next`
+    // before it became a zombie, and `children` was this PCB's only other
+    // owner, so this must be the last `Arc`
+    assert_eq!(Arc::strong_count(&child), 1);
+    let found_pid = child.getpid();
+    let exit_code = child.inner_exclusive_access().exit_code;
+    (found_pid as isize, exit_code)
+}
+
+/// Wait for thread `tid` of the current process to exit, reclaiming its
+/// `TaskControlBlock` and returning its exit code. Returns -1 if `tid` does
+/// not name another thread of this process; otherwise blocks (via
+/// `block_current_and_run_next`) until `tid` exits and `exit_current_and_run_next`
+/// wakes this caller back up, instead of the caller having to poll.
+pub fn waittid(tid: usize) -> isize {
+    loop {
+        let task = current_task().unwrap();
+        let process = task.process();
+        if task.tid() == tid {
+            // a thread cannot wait for itself
+            return -1;
+        }
 
-    for (syscall_id, times) in syscall_times_map {
-        syscall_times[syscall_id] = times as u32;
+        let mut process_inner = process.inner_exclusive_access();
+        let Some(Some(waited_task)) = process_inner.tasks.get(tid) else {
+            return -1;
+        };
+        if !waited_task.inner_exclusive_access().is_zombie() {
+            drop(process_inner);
+            block_current_and_run_next(tid);
+            continue;
+        }
+        let exit_code = waited_task.inner_exclusive_access().exit_code;
+        // reap the exited thread: free its tid slot and kernel stack
+        process_inner.tasks[tid] = None;
+        return exit_code as isize;
     }
-    syscall_times
 }
-/// Add syscall times to current task;
-pub fn add_current_task_syscall_times(syscall_id: usize) {
-    TASK_MANAGER.add_current_task_syscall_times(syscall_id);
+
+lazy_static! {
+    /// Tasks parked on a device/resource wait queue, keyed by the resource id
+    /// they are waiting on. A task lives here instead of the `TASK_MANAGER`
+    /// ready queue while `Blocked`, so the scheduler never dispatches it.
+    static ref WAIT_QUEUES: UPSafeCell<BTreeMap<usize, Vec<Arc<TaskControlBlock>>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Mark the current task `Blocked` on `resource_id` and schedule the next
+/// `Ready` task. The task is parked on `WAIT_QUEUES` rather than the ready
+/// queue, so it sits out scheduling until a matching `wake` call.
+pub fn block_current_and_run_next(resource_id: usize) {
+    let task = take_current_task().unwrap();
+
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.task_status = TaskStatus::Blocked;
+    drop(task_inner);
+
+    WAIT_QUEUES
+        .exclusive_access()
+        .entry(resource_id)
+        .or_insert_with(Vec::new)
+        .push(task);
+
+    schedule(task_cx_ptr);
+}
+
+/// Wake every task blocked on `resource_id`, moving it from `WAIT_QUEUES`
+/// back onto the ready queue as `Ready`. Called from an interrupt or driver
+/// once the awaited resource becomes available.
+pub fn wake(resource_id: usize) {
+    let Some(tasks) = WAIT_QUEUES.exclusive_access().remove(&resource_id) else {
+        return;
+    };
+    for task in tasks {
+        task.inner_exclusive_access().task_status = TaskStatus::Ready;
+        add_task(task);
+    }
 }
-/// Get the task syscall list of the current task
-// pub fn current_task_syscall_list() -> Vec<SyscallInfo> {
-//     TASK_MANAGER.get_current_task_syscall_list()
-// }
-/// Add syscall info to current task;
-// pub fn add_current_task_syscall_info(syscall_info: SyscallInfo) {
-//     TASK_MANAGER.add_current_task_syscall_info(syscall_info);
-// }
-
-/// Get the first dispatched time of the current task
-pub fn current_task_first_dispatched_time() -> usize {
-    TASK_MANAGER.get_current_task_first_dispatched_time()
-}
\ No newline at end of file
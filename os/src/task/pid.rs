@@ -0,0 +1,115 @@
+//! Implementation of [`RecycleAllocator`], [`PidAllocator`] and [`KernelStack`]
+use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE};
+use crate::mm::{MapPermission, VirtAddr, KERNEL_SPACE};
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// A generic recycling id allocator: hands out the most recently recycled id
+/// if there is one, and only otherwise grows `current`. [`PidAllocator`],
+/// the kernel-stack slot allocator below, and each process's own `tid`
+/// allocator are all instances of this same scheme.
+pub struct RecycleAllocator {
+    current: usize,
+    recycled: Vec<usize>,
+}
+
+impl RecycleAllocator {
+    /// Create an empty allocator starting at id 0
+    pub fn new() -> Self {
+        Self {
+            current: 0,
+            recycled: Vec::new(),
+        }
+    }
+    /// Allocate an id
+    pub fn alloc(&mut self) -> usize {
+        if let Some(id) = self.recycled.pop() {
+            id
+        } else {
+            self.current += 1;
+            self.current - 1
+        }
+    }
+    /// Recycle an id
+    pub fn dealloc(&mut self, id: usize) {
+        assert!(id < self.current);
+        assert!(
+            !self.recycled.iter().any(|i| *i == id),
+            "id {} has been deallocated!",
+            id
+        );
+        self.recycled.push(id);
+    }
+}
+
+lazy_static! {
+    static ref PID_ALLOCATOR: UPSafeCell<RecycleAllocator> =
+        unsafe { UPSafeCell::new(RecycleAllocator::new()) };
+}
+
+/// A handle to an allocated pid, recycling the id back to `PID_ALLOCATOR` on
+/// drop so an exited process's pid can be reused.
+pub struct PidHandle(pub usize);
+
+impl Drop for PidHandle {
+    fn drop(&mut self) {
+        PID_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
+
+/// Allocate a new pid
+pub fn pid_alloc() -> PidHandle {
+    PidHandle(PID_ALLOCATOR.exclusive_access().alloc())
+}
+
+lazy_static! {
+    /// Allocator handing out kernel-stack slot ids, independent from pids so a
+    /// kernel stack can be reserved before its owning process's pid is known.
+    /// Threads get their own slot too, since each thread has its own kernel stack.
+    static ref KSTACK_ID_ALLOCATOR: UPSafeCell<RecycleAllocator> =
+        unsafe { UPSafeCell::new(RecycleAllocator::new()) };
+}
+
+/// Return the (bottom, top) virtual address range of the `id`-th kernel
+/// stack slot below the trampoline page, each slot separated by a guard page.
+fn kernel_stack_position(id: usize) -> (usize, usize) {
+    let top = TRAMPOLINE - id * (KERNEL_STACK_SIZE + PAGE_SIZE);
+    let bottom = top - KERNEL_STACK_SIZE;
+    (bottom, top)
+}
+
+/// Kernel stack for a task living in kernel space, mapped into
+/// `KERNEL_SPACE` and torn down automatically when dropped.
+pub struct KernelStack(usize);
+
+/// Allocate and map a fresh kernel stack.
+pub fn kstack_alloc() -> KernelStack {
+    let kstack_id = KSTACK_ID_ALLOCATOR.exclusive_access().alloc();
+    let (kstack_bottom, kstack_top) = kernel_stack_position(kstack_id);
+    KERNEL_SPACE.exclusive_access().insert_framed_area(
+        VirtAddr::from(kstack_bottom),
+        VirtAddr::from(kstack_top),
+        MapPermission::R | MapPermission::W,
+    );
+    KernelStack(kstack_id)
+}
+
+impl KernelStack {
+    /// Get the top virtual address of the kernel stack
+    pub fn get_top(&self) -> usize {
+        let (_, kstack_top) = kernel_stack_position(self.0);
+        kstack_top
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        let (kstack_bottom, _) = kernel_stack_position(self.0);
+        let kstack_bottom_va: VirtAddr = kstack_bottom.into();
+        KERNEL_SPACE
+            .exclusive_access()
+            .remove_area_with_start_vpn(kstack_bottom_va.into());
+        KSTACK_ID_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
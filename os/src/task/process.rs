@@ -0,0 +1,288 @@
+//! Types related to process management.
+//!
+//! A [`ProcessControlBlock`] owns the resources a process's threads share: the
+//! `memory_set`, the `fd_table`, and the parent/children links. Each
+//! schedulable thread of the process is a [`TaskControlBlock`] living in
+//! `tasks`, indexed by its `tid`.
+use super::pid::RecycleAllocator;
+use super::{pid_alloc, PidHandle, TaskControlBlock};
+use crate::config::{PAGE_SIZE, TRAP_CONTEXT_BASE};
+use crate::fs::{File, Stdin, Stdout};
+use crate::mm::{MapPermission, MemorySet, VirtAddr};
+use crate::sync::UPSafeCell;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefMut;
+
+/// Process control block structure
+pub struct ProcessControlBlock {
+    /// Process identifier
+    pub pid: PidHandle,
+    /// Mutable
+    inner: UPSafeCell<ProcessControlBlockInner>,
+}
+
+pub struct ProcessControlBlockInner {
+    /// Set once the process has exited; the `exit_code` below is then valid
+    pub is_zombie: bool,
+    /// Application address space, shared by every thread of the process
+    pub memory_set: MemorySet,
+    /// Parent process of the current process.
+    /// Weak will not affect the reference count of the parent
+    pub parent: Option<Weak<ProcessControlBlock>>,
+    /// A vector containing PCBs of all child processes of the current process
+    pub children: Vec<Arc<ProcessControlBlock>>,
+    /// It is set when active exit or execution error occurs
+    pub exit_code: i32,
+    /// Open file descriptor table. The slot index is the fd returned to
+    /// userspace; a `None` slot is free for `alloc_fd`/`open`/`dup` to reuse.
+    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    /// Every thread belonging to this process, indexed by `tid`; a `None`
+    /// slot is a tid that has been reaped (via `sys_waittid`) and can be
+    /// handed out again
+    pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
+    /// Allocator for this process's own thread ids
+    pub tid_allocator: RecycleAllocator,
+    /// Heap bottom
+    pub heap_bottom: usize,
+    /// Program break
+    pub program_brk: usize,
+}
+
+impl ProcessControlBlockInner {
+    /// get the user token (`satp` value) of the process's address space
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set.token()
+    }
+    pub fn is_zombie(&self) -> bool {
+        self.is_zombie
+    }
+    /// Allocate a new file descriptor: the lowest free slot, extending the
+    /// table if every existing slot is in use.
+    pub fn alloc_fd(&mut self) -> usize {
+        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
+            fd
+        } else {
+            self.fd_table.push(None);
+            self.fd_table.len() - 1
+        }
+    }
+    /// Allocate a tid for a new thread of this process
+    pub fn alloc_tid(&mut self) -> usize {
+        self.tid_allocator.alloc()
+    }
+    /// Recycle a tid once its thread's `ThreadUserRes` is dropped
+    pub fn dealloc_tid(&mut self, tid: usize) {
+        self.tid_allocator.dealloc(tid)
+    }
+}
+
+impl ProcessControlBlock {
+    /// Get the mutable reference of the inner PCB
+    pub fn inner_exclusive_access(&self) -> RefMut<'_, ProcessControlBlockInner> {
+        self.inner.exclusive_access()
+    }
+
+    /// Create a new process, together with its main thread (tid 0)
+    ///
+    /// Used to create initproc, and by [`Self::spawn`]
+    pub fn new(elf_data: &[u8]) -> Arc<Self> {
+        // memory_set with elf program headers/trampoline/trap context/user stack
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let process = Arc::new(Self {
+            pid: pid_alloc(),
+            inner: unsafe {
+                UPSafeCell::new(ProcessControlBlockInner {
+                    is_zombie: false,
+                    memory_set,
+                    parent: None,
+                    children: Vec::new(),
+                    exit_code: 0,
+                    fd_table: vec![
+                        // 0 -> stdin
+                        Some(Arc::new(Stdin)),
+                        // 1 -> stdout
+                        Some(Arc::new(Stdout)),
+                        // 2 -> stderr
+                        Some(Arc::new(Stdout)),
+                    ],
+                    tasks: Vec::new(),
+                    tid_allocator: RecycleAllocator::new(),
+                    heap_bottom: user_sp,
+                    program_brk: user_sp,
+                })
+            },
+        });
+        // the main thread's stack/trap context were already mapped by
+        // `from_elf`, so it does not go through `ThreadUserRes::alloc_user_res`
+        let task = Arc::new(TaskControlBlock::new(Arc::clone(&process), user_sp, false));
+        task.prepare_init_context(entry_point, user_sp);
+        process.inner_exclusive_access().tasks.push(Some(Arc::clone(&task)));
+        process
+    }
+
+    /// Load a new elf to replace the original application address space and
+    /// start execution. Every thread but the main thread (`tasks[0]`) is torn
+    /// down, and the main thread restarts at the new entry point.
+    pub fn exec(self: &Arc<Self>, elf_data: &[u8]) {
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+
+        // drop every thread but the main thread against the *old* memory_set,
+        // then release the lock before `ThreadUserRes::drop` runs so it can
+        // reacquire `inner_exclusive_access` to unmap itself
+        let discarded = self.inner_exclusive_access().tasks.split_off(1);
+        drop(discarded);
+
+        let mut inner = self.inner_exclusive_access();
+        inner.memory_set = memory_set;
+        inner.heap_bottom = user_sp;
+        inner.program_brk = user_sp;
+        let trap_cx_ppn = inner
+            .memory_set
+            .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
+            .unwrap()
+            .ppn();
+        let task = inner.tasks[0].as_ref().unwrap().clone();
+        drop(inner);
+        // the new memory_set's trap-context page lives at a fresh physical
+        // frame; refresh the cached ppn before touching the trap context
+        task.inner_exclusive_access().trap_cx_ppn = Some(trap_cx_ppn);
+        task.prepare_init_context(entry_point, user_sp);
+    }
+
+    /// The calling thread forks its process: copy-on-write the address space
+    /// and copy the fd table into a new child process whose only thread
+    /// mirrors this one.
+    ///
+    /// `MemorySet::from_existed_user_cow` shares frames instead of copying
+    /// them: every writable user leaf in both the parent's and child's page
+    /// tables is remapped read-only with the `COW` bit set and the frame's
+    /// `crate::mm::frame_allocator` refcount bumped, so a private copy is
+    /// only materialized lazily, on the first store page fault either side
+    /// takes (see `PageTable::handle_cow_fault`).
+    pub fn fork(self: &Arc<Self>, caller: &Arc<TaskControlBlock>) -> Arc<Self> {
+        let mut parent_inner = self.inner_exclusive_access();
+        let memory_set = MemorySet::from_existed_user_cow(&mut parent_inner.memory_set);
+        let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
+        for fd in parent_inner.fd_table.iter() {
+            new_fd_table.push(fd.clone());
+        }
+        let child = Arc::new(Self {
+            pid: pid_alloc(),
+            inner: unsafe {
+                UPSafeCell::new(ProcessControlBlockInner {
+                    is_zombie: false,
+                    memory_set,
+                    parent: Some(Arc::downgrade(self)),
+                    children: Vec::new(),
+                    exit_code: 0,
+                    fd_table: new_fd_table,
+                    tasks: Vec::new(),
+                    tid_allocator: RecycleAllocator::new(),
+                    heap_bottom: parent_inner.heap_bottom,
+                    program_brk: parent_inner.program_brk,
+                })
+            },
+        });
+        parent_inner.children.push(Arc::clone(&child));
+        drop(parent_inner);
+
+        // the caller becomes the child's main (and only) thread; its stack
+        // and trap context already live inside the copied memory_set
+        let ustack_base = caller.ustack_base();
+        let child_task = Arc::new(TaskControlBlock::new(Arc::clone(&child), ustack_base, false));
+        let trap_cx_ppn = child_task.inner_exclusive_access().trap_cx_ppn.unwrap();
+        let trap_cx = trap_cx_ppn.get_mut();
+        *trap_cx = *caller.inner_exclusive_access().get_trap_cx();
+        trap_cx.kernel_sp = child_task.kernel_stack_top();
+        child.inner_exclusive_access().tasks.push(Some(child_task));
+        child
+    }
+
+    /// spawn a new process running `elf_data`, as a child of `self`
+    pub fn spawn(self: &Arc<Self>, elf_data: &[u8]) -> Arc<Self> {
+        let new_process = ProcessControlBlock::new(elf_data);
+        new_process.inner_exclusive_access().parent = Some(Arc::downgrade(self));
+        self.inner_exclusive_access().children.push(Arc::clone(&new_process));
+        new_process
+    }
+
+    /// get pid of process
+    pub fn getpid(&self) -> usize {
+        self.pid.0
+    }
+
+    /// change the location of the program break. return None if failed.
+    pub fn change_program_brk(&self, size: i32) -> Option<usize> {
+        let mut inner = self.inner_exclusive_access();
+        let heap_bottom = inner.heap_bottom;
+        let old_break = inner.program_brk;
+        let new_brk = inner.program_brk as isize + size as isize;
+        if new_brk < heap_bottom as isize {
+            return None;
+        }
+        let result = if size < 0 {
+            inner
+                .memory_set
+                .shrink_to(VirtAddr(heap_bottom), VirtAddr(new_brk as usize))
+        } else {
+            inner
+                .memory_set
+                .append_to(VirtAddr(heap_bottom), VirtAddr(new_brk as usize))
+        };
+        if result {
+            inner.program_brk = new_brk as usize;
+            Some(old_break)
+        } else {
+            None
+        }
+    }
+
+    /// Alloc memory
+    pub fn alloc_memory(&self, start: usize, len: usize, port: usize) -> isize {
+        if start % PAGE_SIZE != 0 {
+            return -1;
+        }
+
+        if port & !0x7 != 0 || port & 0x7 == 0 {
+            return -1;
+        }
+
+        let start_va = VirtAddr::from(start);
+        let end_va = VirtAddr::from(start + len);
+
+        let mut inner = self.inner_exclusive_access();
+
+        if inner.memory_set.is_allocated(start_va, end_va) {
+            return -1;
+        }
+
+        let permission = MapPermission::from_bits((port as u8) << 1).unwrap() | MapPermission::U;
+
+        inner.memory_set.insert_framed_area(start_va, end_va, permission);
+        0
+    }
+
+    /// Dealloc memory
+    pub fn dealloc(&self, start: usize, len: usize) -> isize {
+        if start % PAGE_SIZE != 0 {
+            return -1;
+        }
+
+        let start_va = VirtAddr::from(start);
+        let end_va = VirtAddr::from(start + len);
+
+        if !start_va.aligned() {
+            return -1;
+        }
+
+        if !end_va.aligned() {
+            return -1;
+        }
+
+        let mut inner = self.inner_exclusive_access();
+        inner.memory_set.remove_framed_area(start_va, end_va);
+        0
+    }
+}
@@ -0,0 +1,113 @@
+//! Implementation of [`Processor`] and Intersection of control flow
+use super::__switch;
+use super::{fetch_min_task, TaskStatus};
+use super::{TaskContext, TaskControlBlock};
+use crate::sync::UPSafeCell;
+use crate::trap::TrapContext;
+use alloc::sync::Arc;
+use lazy_static::*;
+
+/// Processor management structure
+pub struct Processor {
+    /// The thread currently executing on the current processor
+    current: Option<Arc<TaskControlBlock>>,
+    /// The basic control flow of each core, helping to select and switch threads
+    idle_task_cx: TaskContext,
+}
+
+impl Processor {
+    /// Create an empty Processor
+    pub fn new() -> Self {
+        Self {
+            current: None,
+            idle_task_cx: TaskContext::zero_init(),
+        }
+    }
+    /// Get mutable reference to `idle_task_cx`
+    fn get_idle_task_cx_ptr(&mut self) -> *mut TaskContext {
+        &mut self.idle_task_cx as *mut _
+    }
+    /// Take the current task out, leaving a None in its place
+    pub fn take_current(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.current.take()
+    }
+    /// Get a copy of the current task
+    pub fn current(&self) -> Option<Arc<TaskControlBlock>> {
+        self.current.as_ref().map(Arc::clone)
+    }
+}
+
+lazy_static! {
+    /// The processor running the current task, through `UPSafeCell`
+    pub static ref PROCESSOR: UPSafeCell<Processor> = unsafe { UPSafeCell::new(Processor::new()) };
+}
+
+/// The main schedule loop: repeatedly fetch the `Ready` task with the
+/// smallest stride from the manager and `__switch` into it, giving each
+/// task CPU share proportional to its priority instead of flat round-robin.
+pub fn run_tasks() {
+    loop {
+        let mut processor = PROCESSOR.exclusive_access();
+        if let Some(task) = fetch_min_task() {
+            let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
+            let mut task_inner = task.inner_exclusive_access();
+            let next_task_cx_ptr = &task_inner.task_cx as *const TaskContext;
+            task_inner.task_status = TaskStatus::Running;
+            task_inner.task_info.set_timestamp_is_first_dispatched();
+            // this thread's kernel-time accumulator resumes from here, not
+            // from however long it sat `Ready` in the queue
+            task_inner.task_info.last_switch_time = crate::timer::get_time_us();
+            drop(task_inner);
+            processor.current = Some(task);
+            drop(processor);
+            unsafe {
+                __switch(idle_task_cx_ptr, next_task_cx_ptr);
+            }
+        } else {
+            // No `Ready` task right now; some tasks may simply be `Blocked`
+            // on I/O rather than the app set being exhausted, so idle until
+            // the next interrupt (e.g. a device completion calling `wake`)
+            // instead of busy-spinning or panicking.
+            drop(processor);
+            #[cfg(target_arch = "riscv64")]
+            unsafe {
+                core::arch::asm!("wfi");
+            }
+        }
+    }
+}
+
+/// Take the current task, leaving a None in its place
+pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
+    PROCESSOR.exclusive_access().take_current()
+}
+
+/// Get a copy of the current task
+pub fn current_task() -> Option<Arc<TaskControlBlock>> {
+    PROCESSOR.exclusive_access().current()
+}
+
+/// Get the current user token (i.e. `satp`)
+pub fn current_user_token() -> usize {
+    let task = current_task().unwrap();
+    task.get_user_token()
+}
+
+/// Get the mutable reference to the current task's trap context
+pub fn current_trap_cx() -> &'static mut TrapContext {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .get_trap_cx()
+}
+
+/// Return to the idle control flow, usually invoked to relinquish the CPU
+/// when a task suspends or exits.
+pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
+    let mut processor = PROCESSOR.exclusive_access();
+    let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
+    drop(processor);
+    unsafe {
+        __switch(switched_task_cx_ptr, idle_task_cx_ptr);
+    }
+}
@@ -1,25 +1,25 @@
-//! Types related to task management & Functions for completely changing TCB
+//! Types related to task (thread) management & Functions for completely changing TCB
 use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use super::TaskContext;
-use super::{kstack_alloc, pid_alloc, KernelStack, PidHandle};
-use crate::config::{MAX_SYSCALL_NUM, PAGE_SIZE, TRAP_CONTEXT_BASE};
-use crate::mm::{MapPermission, MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
+use super::{kstack_alloc, KernelStack};
+use super::{ProcessControlBlock, ThreadUserRes};
+use crate::config::MAX_SYSCALL_NUM;
+use crate::mm::{PhysPageNum, KERNEL_SPACE};
 use crate::sync::UPSafeCell;
 use crate::syscall::SyscallInfo;
 use crate::trap::{trap_handler, TrapContext};
-use alloc::sync::{Arc, Weak};
 use core::cell::RefMut;
 
-/// Task control block structure
+/// Task control block structure: one schedulable thread of a process
 ///
 /// Directly save the contents that will not change during running
 pub struct TaskControlBlock {
-    // Immutable
-    /// Process identifier
-    pub pid: PidHandle,
+    /// Immutable: the process this thread belongs to
+    pub process: Weak<ProcessControlBlock>,
 
-    /// Kernel stack corresponding to PID
+    /// Kernel stack for this thread
     pub kernel_stack: KernelStack,
 
     /// Mutable
@@ -31,141 +31,59 @@ impl TaskControlBlock {
     pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
         self.inner.exclusive_access()
     }
-    /// Get the address of app's page table
-    pub fn get_user_token(&self) -> usize {
-        let inner = self.inner_exclusive_access();
-        inner.memory_set.token()
-    }
-}
-
-pub struct TaskControlBlockInner {
-    /// The physical page number of the frame where the trap context is placed
-    pub trap_cx_ppn: PhysPageNum,
-
-    /// Application data can only appear in areas
-    /// where the application address space is lower than base_size
-    pub base_size: usize,
-
-    /// Save task context
-    pub task_cx: TaskContext,
-
-    /// Maintain the execution status of the current process
-    pub task_status: TaskStatus,
-
-    /// scheduling priority
-    pub priority: isize,
-
-    /// current stride
-    pub stride: usize,
-
-    /// Task information
-    pub task_info: TaskInfo,
-
-    /// Application address space
-    pub memory_set: MemorySet,
-
-    /// Parent process of the current process.
-    /// Weak will not affect the reference count of the parent
-    pub parent: Option<Weak<TaskControlBlock>>,
-
-    /// A vector containing TCBs of all child processes of the current process
-    pub children: Vec<Arc<TaskControlBlock>>,
-
-    /// It is set when active exit or execution error occurs
-    pub exit_code: i32,
-
-    /// Heap bottom
-    pub heap_bottom: usize,
-
-    /// Program break
-    pub program_brk: usize,
-}
-
-impl TaskControlBlockInner {
-    /// get the trap context
-    pub fn get_trap_cx(&self) -> &'static mut TrapContext {
-        self.trap_cx_ppn.get_mut()
+    /// The process this thread belongs to
+    pub fn process(&self) -> Arc<ProcessControlBlock> {
+        self.process.upgrade().unwrap()
     }
-    /// get the user token
+    /// Get the address of the owning process's page table
     pub fn get_user_token(&self) -> usize {
-        self.memory_set.token()
+        self.process().inner_exclusive_access().memory_set.token()
     }
-    fn get_status(&self) -> TaskStatus {
-        self.task_status
+    /// This thread's id within its process
+    pub fn tid(&self) -> usize {
+        self.inner_exclusive_access().res.as_ref().unwrap().tid
     }
-    pub fn is_zombie(&self) -> bool {
-        self.get_status() == TaskStatus::Zombie
+    /// Base virtual address this thread's user stack is offset from
+    pub fn ustack_base(&self) -> usize {
+        self.inner_exclusive_access().res.as_ref().unwrap().ustack_base
+    }
+    /// Top virtual address of this thread's kernel stack
+    pub fn kernel_stack_top(&self) -> usize {
+        self.kernel_stack.get_top()
     }
-}
 
-/// TCBImp is the implementation of TaskControlBlock
-impl TaskControlBlock {
-    /// Create a new process
-    ///
-    /// At present, it is only used for the creation of initproc
-    pub fn new(elf_data: &[u8]) -> Self {
-        // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
-            .unwrap()
-            .ppn();
-        // alloc a pid and a kernel stack in kernel space
-        let pid_handle = pid_alloc();
+    /// Create a new thread belonging to `process`. `alloc_user_res` should
+    /// be false only for the process's main thread (tid 0), whose stack and
+    /// trap context `MemorySet::from_elf` already mapped.
+    pub fn new(process: Arc<ProcessControlBlock>, ustack_base: usize, alloc_user_res: bool) -> Self {
+        let res = ThreadUserRes::new(&process, ustack_base, alloc_user_res);
+        let trap_cx_ppn = res.trap_cx_ppn(&process.inner_exclusive_access().memory_set);
         let kernel_stack = kstack_alloc();
         let kernel_stack_top = kernel_stack.get_top();
-        // push a task context which goes to trap_return to the top of kernel stack
-        let task_control_block = Self {
-            pid: pid_handle,
+        Self {
+            process: Arc::downgrade(&process),
             kernel_stack,
             inner: unsafe {
                 UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: user_sp,
+                    res: Some(res),
+                    trap_cx_ppn: Some(trap_cx_ppn),
                     task_cx: TaskContext::goto_trap_return(kernel_stack_top),
                     task_status: TaskStatus::Ready,
                     priority: 16,
                     stride: 0,
-                    memory_set,
-                    parent: None,
-                    children: Vec::new(),
                     exit_code: 0,
-                    heap_bottom: user_sp,
-                    program_brk: user_sp,
                     task_info: TaskInfo::default(),
                 })
             },
-        };
-        // prepare TrapContext in user space
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            kernel_stack_top,
-            trap_handler as usize,
-        );
-        task_control_block
+        }
     }
 
-    /// Load a new elf to replace the original application address space and start execution
-    pub fn exec(&self, elf_data: &[u8]) {
-        // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
-            .unwrap()
-            .ppn();
-
-        // **** access current TCB exclusively
-        let mut inner = self.inner_exclusive_access();
-        // substitute memory_set
-        inner.memory_set = memory_set;
-        // update trap_cx ppn
-        inner.trap_cx_ppn = trap_cx_ppn;
-        // initialize base_size
-        inner.base_size = user_sp;
-        // initialize trap_cx
+    /// Set up this thread's trap context to start running `entry_point` with
+    /// stack pointer `user_sp` (the process's main thread passes the address
+    /// `from_elf` chose; a spawned thread passes its `ThreadUserRes`'s own
+    /// `ustack_top()`).
+    pub fn prepare_init_context(&self, entry_point: usize, user_sp: usize) {
+        let inner = self.inner_exclusive_access();
         let trap_cx = inner.get_trap_cx();
         *trap_cx = TrapContext::app_init_context(
             entry_point,
@@ -174,148 +92,48 @@ impl TaskControlBlock {
             self.kernel_stack.get_top(),
             trap_handler as usize,
         );
-        // **** release inner automatically
     }
 
-    /// parent process fork the child process
-    pub fn fork(self: &Arc<Self>) -> Arc<Self> {
-        // ---- access parent PCB exclusively
-        let mut parent_inner = self.inner_exclusive_access();
-        // copy user space(include trap context)
-        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
-            .unwrap()
-            .ppn();
-        // alloc a pid and a kernel stack in kernel space
-        let pid_handle = pid_alloc();
-        let kernel_stack = kstack_alloc();
-        let kernel_stack_top = kernel_stack.get_top();
-        let task_control_block = Arc::new(TaskControlBlock {
-            pid: pid_handle,
-            kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: parent_inner.base_size,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    priority: 16,
-                    stride: 0,
-                    memory_set,
-                    parent: Some(Arc::downgrade(self)),
-                    children: Vec::new(),
-                    exit_code: 0,
-                    heap_bottom: parent_inner.heap_bottom,
-                    program_brk: parent_inner.program_brk,
-                    task_info: TaskInfo::default(),
-                })
-            },
-        });
-        // add child
-        parent_inner.children.push(task_control_block.clone());
-        // modify kernel_sp in trap_cx
-        // **** access child PCB exclusively
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-        trap_cx.kernel_sp = kernel_stack_top;
-        // return
-        task_control_block
-        // **** release child PCB
-        // ---- release parent PCB
+    /// The calling thread forks its process; see [`ProcessControlBlock::fork`].
+    pub fn fork(self: &Arc<Self>) -> Arc<ProcessControlBlock> {
+        self.process().fork(self)
     }
 
-    /// spawn a new process
-    pub fn spawn(&self, elf_data: &[u8]) -> Arc<Self> {
-        let new_task = Arc::new(TaskControlBlock::new(elf_data));
-        self.inner_exclusive_access().children.push(new_task.clone());
-        new_task
+    /// Load a new elf, replacing the owning process's address space; see
+    /// [`ProcessControlBlock::exec`].
+    pub fn exec(self: &Arc<Self>, elf_data: &[u8]) {
+        self.process().exec(elf_data)
     }
 
-    /// get pid of process
+    /// spawn a new process running `elf_data`; see [`ProcessControlBlock::spawn`].
+    pub fn spawn(self: &Arc<Self>, elf_data: &[u8]) -> Arc<ProcessControlBlock> {
+        self.process().spawn(elf_data)
+    }
+
+    /// get pid of the owning process
     pub fn getpid(&self) -> usize {
-        self.pid.0
+        self.process().getpid()
     }
 
-    /// change the location of the program break. return None if failed.
+    /// change the location of the owning process's program break. return None if failed.
     pub fn change_program_brk(&self, size: i32) -> Option<usize> {
-        let mut inner = self.inner_exclusive_access();
-        let heap_bottom = inner.heap_bottom;
-        let old_break = inner.program_brk;
-        let new_brk = inner.program_brk as isize + size as isize;
-        if new_brk < heap_bottom as isize {
-            return None;
-        }
-        let result = if size < 0 {
-            inner
-                .memory_set
-                .shrink_to(VirtAddr(heap_bottom), VirtAddr(new_brk as usize))
-        } else {
-            inner
-                .memory_set
-                .append_to(VirtAddr(heap_bottom), VirtAddr(new_brk as usize))
-        };
-        if result {
-            inner.program_brk = new_brk as usize;
-            Some(old_break)
-        } else {
-            None
-        }
+        self.process().change_program_brk(size)
     }
 
-    /// set the priority of the process
+    /// set the priority of this thread
     pub fn set_priority(&self, prio: isize) {
         assert!(prio > 1, "priority should be larger than 1");
         self.inner_exclusive_access().priority = prio
     }
 
-
-    /// Alloc memory
+    /// Alloc memory in the owning process's address space
     pub fn alloc_memory(&self, start: usize, len: usize, port: usize) -> isize {
-        if start % PAGE_SIZE != 0 {
-            return -1;
-        }
-
-        if port & !0x7 != 0 || port & 0x7 == 0 {
-            return -1;
-        }
-
-        let start_va = VirtAddr::from(start);
-        let end_va = VirtAddr::from(start + len);
-
-        let mut inner = self.inner.exclusive_access();
-    
-
-        if inner.memory_set.is_allocated(start_va, end_va) {
-            return -1;
-        }
-
-        let permission = MapPermission::from_bits((port as u8) << 1).unwrap() | MapPermission::U;
-
-        inner.memory_set.insert_framed_area(start_va, end_va, permission);
-        0
-
+        self.process().alloc_memory(start, len, port)
     }
 
-    /// Dealloc memory
+    /// Dealloc memory in the owning process's address space
     pub fn dealloc(&self, start: usize, len: usize) -> isize {
-        if start % PAGE_SIZE != 0 {
-            return -1;
-        }
-
-        let start_va = VirtAddr::from(start);
-        let end_va = VirtAddr::from(start + len);
-
-        if !start_va.aligned() {
-            return -1;
-        }
-
-        if !end_va.aligned() {
-            return -1;
-        }
-
-        let mut inner = self.inner.exclusive_access();
-        inner.memory_set.remove_framed_area(start_va, end_va);
-        0
+        self.process().dealloc(start, len)
     }
 
     /// get the task status
@@ -344,13 +162,86 @@ impl TaskControlBlock {
         let times = &mut inner.task_info.syscall_times;
         *times.entry(syscall_id).or_default() += 1;
     }
-    
+
     /// add task syscall info
     pub fn add_task_syscall_info(&self, syscall_info: SyscallInfo) {
         let mut inner = self.inner_exclusive_access();
         inner.task_info.syscall_list.push(syscall_info);
     }
 
+    /// total microseconds this thread has spent running in user mode
+    pub fn get_user_time(&self) -> usize {
+        self.inner_exclusive_access().task_info.user_time
+    }
+
+    /// total microseconds this thread has spent running in kernel mode
+    /// (trap handling, syscalls) since it was first dispatched
+    pub fn get_kernel_time(&self) -> usize {
+        self.inner_exclusive_access().task_info.kernel_time
+    }
+
+    /// Charge the time since `last_switch_time` to `user_time`. Called by
+    /// `syscall()` on entry, before the syscall is dispatched; traps from
+    /// user mode other than a syscall do not charge it.
+    pub fn charge_user_time(&self) {
+        let mut inner = self.inner_exclusive_access();
+        let now = crate::timer::get_time_us();
+        inner.task_info.user_time += now - inner.task_info.last_switch_time;
+        inner.task_info.last_switch_time = now;
+    }
+
+    /// Charge the time since `last_switch_time` to `kernel_time`. Called on
+    /// trap return, immediately before control returns to user mode.
+    pub fn charge_kernel_time(&self) {
+        let mut inner = self.inner_exclusive_access();
+        let now = crate::timer::get_time_us();
+        inner.task_info.kernel_time += now - inner.task_info.last_switch_time;
+        inner.task_info.last_switch_time = now;
+    }
+
+}
+
+pub struct TaskControlBlockInner {
+    /// This thread's user stack and trap-context page, `None` once the
+    /// thread has exited and had them reclaimed
+    pub res: Option<ThreadUserRes>,
+
+    /// The physical page number of the frame where the trap context is
+    /// placed
+    pub trap_cx_ppn: Option<PhysPageNum>,
+
+    /// Save task context
+    pub task_cx: TaskContext,
+
+    /// Maintain the execution status of the current thread
+    pub task_status: TaskStatus,
+
+    /// scheduling priority
+    pub priority: isize,
+
+    /// current stride
+    pub stride: usize,
+
+    /// It is set when the thread exits; readable once `task_status` is `Zombie`
+    pub exit_code: i32,
+
+    /// Task information
+    pub task_info: TaskInfo,
+}
+
+impl TaskControlBlockInner {
+    /// get the trap context
+    pub fn get_trap_cx(&self) -> &'static mut TrapContext {
+        self.trap_cx_ppn
+            .expect("trap context accessed before it was allocated")
+            .get_mut()
+    }
+    fn get_status(&self) -> TaskStatus {
+        self.task_status
+    }
+    pub fn is_zombie(&self) -> bool {
+        self.get_status() == TaskStatus::Zombie
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -362,6 +253,18 @@ pub struct TaskInfo {
     pub syscall_times: BTreeMap<usize, u32>,
     /// The called syscall list of the task
     pub syscall_list: Vec<SyscallInfo>,
+    /// Remaining ticks in the task's current time slice, counted down by
+    /// `on_tick` and reloaded to `TIME_SLICE` once it reaches zero
+    pub remaining_slice: usize,
+
+    /// Total microseconds spent running in user mode
+    pub user_time: usize,
+    /// Total microseconds spent running in kernel mode (trap handling, syscalls)
+    pub kernel_time: usize,
+    /// Timestamp of the last user/kernel mode boundary crossed, or the last
+    /// time this task was dispatched; the reference point `charge_user_time`/
+    /// `charge_kernel_time` measure elapsed time from
+    pub last_switch_time: usize,
 }
 
 impl TaskInfo {
@@ -371,6 +274,10 @@ impl TaskInfo {
             first_dispatched_time: 0,
             syscall_times: BTreeMap::new(),
             syscall_list: Vec::new(),
+            remaining_slice: crate::config::TIME_SLICE,
+            user_time: 0,
+            kernel_time: 0,
+            last_switch_time: 0,
         }
     }
 
@@ -385,7 +292,7 @@ impl TaskInfo {
 
 
 #[derive(Copy, Clone, PartialEq)]
-/// task status: UnInit, Ready, Running, Exited
+/// task status: UnInit, Ready, Running, Blocked, Zombie
 pub enum TaskStatus {
     /// uninitialized
     /// (only for the task that has not been added to the scheduler)
@@ -394,6 +301,9 @@ pub enum TaskStatus {
     Ready,
     /// running
     Running,
+    /// waiting on a device or other resource; not in the ready queue and
+    /// will not be scheduled again until `wake` moves it back to `Ready`
+    Blocked,
     /// exited
     Zombie,
 }
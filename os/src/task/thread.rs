@@ -0,0 +1,106 @@
+//! Per-thread user resources.
+//!
+//! A process's main thread (`tid == 0`) still loads its user stack and trap
+//! context the way `TaskControlBlock::new` always has (laid out by
+//! `MemorySet::from_elf` at a fixed address), so it skips `alloc_user_res`.
+//! A thread spawned later via `sys_thread_create` has no ELF-provided stack,
+//! so it needs its own slice of user address space carved out on demand: a
+//! `tid`-indexed user stack and trap-context page, both torn down
+//! automatically when the thread exits.
+use super::ProcessControlBlock;
+use crate::config::{PAGE_SIZE, TRAP_CONTEXT_BASE, USER_STACK_SIZE};
+use crate::mm::{MapPermission, MemorySet, PhysPageNum, VirtAddr};
+use alloc::sync::{Arc, Weak};
+
+/// A thread's user-mode resources: its `tid`, a dedicated user stack, and a
+/// dedicated trap-context page, mapped into the owning process's shared
+/// address space.
+///
+/// The stack for tid `k` lives at `ustack_base + k * (USER_STACK_SIZE + PAGE_SIZE)`
+/// (one guard page between threads), and the trap context at
+/// `TRAP_CONTEXT_BASE - k * PAGE_SIZE`, mirroring how the process's own
+/// trap context sits at `TRAP_CONTEXT_BASE` for `tid == 0`.
+pub struct ThreadUserRes {
+    /// thread id, also this thread's index into the stack/trap-context layout
+    pub tid: usize,
+    /// base virtual address all per-thread user stacks are offset from
+    pub ustack_base: usize,
+    /// the process whose address space these resources are mapped into
+    pub process: Weak<ProcessControlBlock>,
+    /// whether this struct mapped its own stack/trap-context page and so
+    /// must unmap it on drop; false for the main thread, whose stack and
+    /// trap context `MemorySet::from_elf` mapped directly
+    owns_mapping: bool,
+}
+
+impl ThreadUserRes {
+    /// Allocate a tid from `process`'s own recycling allocator and, unless
+    /// `alloc_user_res` is false (the process's main thread, whose stack and
+    /// trap context `from_elf` already mapped), map its stack/trap-context
+    /// page into the process's `memory_set`.
+    pub fn new(process: &Arc<ProcessControlBlock>, ustack_base: usize, alloc_user_res: bool) -> Self {
+        let tid = process.inner_exclusive_access().alloc_tid();
+        let res = Self {
+            tid,
+            ustack_base,
+            process: Arc::downgrade(process),
+            owns_mapping: alloc_user_res,
+        };
+        if alloc_user_res {
+            res.alloc_user_res(&mut process.inner_exclusive_access().memory_set);
+        }
+        res
+    }
+
+    /// Bottom virtual address of this thread's user stack
+    pub fn ustack_bottom(&self) -> usize {
+        self.ustack_base + self.tid * (USER_STACK_SIZE + PAGE_SIZE)
+    }
+    /// Top virtual address of this thread's user stack
+    pub fn ustack_top(&self) -> usize {
+        self.ustack_bottom() + USER_STACK_SIZE
+    }
+    /// Bottom virtual address of this thread's trap-context page
+    pub fn trap_cx_bottom(&self) -> usize {
+        TRAP_CONTEXT_BASE - self.tid * PAGE_SIZE
+    }
+
+    fn alloc_user_res(&self, memory_set: &mut MemorySet) {
+        memory_set.insert_framed_area(
+            VirtAddr::from(self.ustack_bottom()),
+            VirtAddr::from(self.ustack_top()),
+            MapPermission::R | MapPermission::W | MapPermission::U,
+        );
+        memory_set.insert_framed_area(
+            VirtAddr::from(self.trap_cx_bottom()),
+            VirtAddr::from(self.trap_cx_bottom() + PAGE_SIZE),
+            MapPermission::R | MapPermission::W,
+        );
+    }
+
+    /// Unmap this thread's stack and trap-context page from `memory_set`.
+    pub fn dealloc_user_res(&self, memory_set: &mut MemorySet) {
+        memory_set.remove_area_with_start_vpn(VirtAddr::from(self.trap_cx_bottom()).into());
+        memory_set.remove_area_with_start_vpn(VirtAddr::from(self.ustack_bottom()).into());
+    }
+
+    /// Physical page backing this thread's trap context
+    pub fn trap_cx_ppn(&self, memory_set: &MemorySet) -> PhysPageNum {
+        memory_set
+            .translate(VirtAddr::from(self.trap_cx_bottom()).into())
+            .unwrap()
+            .ppn()
+    }
+}
+
+impl Drop for ThreadUserRes {
+    fn drop(&mut self) {
+        if let Some(process) = self.process.upgrade() {
+            let mut process_inner = process.inner_exclusive_access();
+            if self.owns_mapping {
+                self.dealloc_user_res(&mut process_inner.memory_set);
+            }
+            process_inner.dealloc_tid(self.tid);
+        }
+    }
+}